@@ -0,0 +1,99 @@
+use std::{collections::BTreeMap, path::Path};
+
+use serde::Serialize;
+use tera::Tera;
+
+use crate::{
+    config::{self, AltConfig},
+    error::Error,
+    instagram,
+    metadata::PostMetadata,
+    MediaType,
+};
+
+/// Chooses the alt text of each image of one post: an override from the
+/// overrides file, then Instagram's accessibility caption, then the fallback template.
+pub(crate) struct AltText<'a> {
+    config: &'a AltConfig,
+    overrides: BTreeMap<String, String>,
+    metadata: Option<&'a PostMetadata>,
+    sentence: String,
+}
+
+// what the fallback template can use
+#[derive(Serialize)]
+struct TemplateContext<'a> {
+    sentence: &'a str,
+    index: usize,
+    count: usize,
+    media_type: MediaType,
+}
+
+impl<'a> AltText<'a> {
+    pub(crate) fn new(
+        config: &'a AltConfig,
+        metadata: Option<&'a PostMetadata>,
+        caption: &str,
+    ) -> Result<AltText<'a>, Error> {
+        let overrides = match &config.overrides {
+            Some(path) => read_overrides(path)?,
+            None => BTreeMap::new(),
+        };
+        Ok(AltText {
+            config,
+            overrides,
+            metadata,
+            sentence: instagram::first_sentence(caption),
+        })
+    }
+
+    /// Alt text of the `index`th (from 1) of `count` assets, `asset` being its filename.
+    pub(crate) fn describe(
+        &self,
+        asset: &str,
+        index: usize,
+        count: usize,
+        media_type: MediaType,
+    ) -> Result<String, Error> {
+        let asset_stem = Path::new(asset)
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or(asset);
+        if let Some(text) = self.overrides.get(asset_stem) {
+            return Ok(text.clone());
+        }
+        if let Some(caption) = self.accessibility_caption(index, count) {
+            return Ok(caption.to_string());
+        }
+
+        let context = TemplateContext {
+            sentence: &self.sentence,
+            index,
+            count,
+            media_type,
+        };
+        let context = tera::Context::from_serialize(context)?;
+        let text = Tera::one_off(&self.config.template, &context, false)?;
+        Ok(text.split_whitespace().collect::<Vec<&str>>().join(" "))
+    }
+
+    // the post's for a single image, the carousel item's otherwise
+    fn accessibility_caption(&self, index: usize, count: usize) -> Option<&str> {
+        let metadata = self.metadata?;
+        let caption = match metadata.children.is_empty() && count == 1 {
+            true => metadata.accessibility_caption.as_ref(),
+            false => index
+                .checked_sub(1)
+                .and_then(|position| metadata.children.get(position))
+                .and_then(|child| child.accessibility_caption.as_ref()),
+        };
+        caption
+            .map(|caption| caption.trim())
+            .filter(|caption| !caption.is_empty())
+    }
+}
+
+/// Read the alt text overrides, a TOML table of text keyed by asset stem.
+pub(crate) fn read_overrides(path: &Path) -> Result<BTreeMap<String, String>, Error> {
+    Ok(config::read_toml(path)?)
+}
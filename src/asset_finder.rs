@@ -5,83 +5,143 @@ use std::{
     fs,
     path::{Path, PathBuf},
     process::{exit, id},
+    sync::Arc,
 };
 
 use actix::{
     Actor, Context, ContextFutureSpawner, Handler, Message, ResponseActFuture, System, WrapFuture,
 };
 use chrono::NaiveDateTime;
-use dotenvy_macro::dotenv;
-use glob::glob;
+use glob::{glob, Pattern};
 use lazy_static::lazy_static;
 use log::{debug, error, info};
 use regex::{Captures, Regex};
 
+use crate::{config::Config, error::Error, metadata};
+
 #[derive(Message)]
-#[rtype(result = "Result<(HashMap<String, String>, Vec<String>), std::io::Error>")]
+#[rtype(result = "Result<(HashMap<String, String>, Vec<String>), Error>")]
 pub(crate) struct AssetMessage(pub HashMap<String, String>);
 
-pub(crate) struct AssetFinder;
+pub(crate) struct AssetFinder {
+    pub config: Arc<Config>,
+}
 
 impl Actor for AssetFinder {
     type Context = Context<Self>;
 }
 
 impl Handler<AssetMessage> for AssetFinder {
-    type Result = Result<(HashMap<String, String>, Vec<String>), std::io::Error>; // <- Message response type
+    type Result = Result<(HashMap<String, String>, Vec<String>), Error>; // <- Message response type
 
     fn handle(&mut self, msg: AssetMessage, _ctx: &mut Context<Self>) -> Self::Result {
         let mut dd = msg.0;
 
-
         // find the correct list of media files, i.e. remove any images that have a corresponding video
-        let (image_files, video_files) = find_media_files(dd.get("post_path").unwrap().to_string());
+        let (image_files, video_files) =
+            find_media_files(&self.config, dd["post_path"].to_string());
 
-        let media_files = merge_media_file_lists(image_files, video_files);
+        let mut media_files = merge_media_file_lists(image_files, video_files);
+        if media_files.is_empty() {
+            return Err(Error::NoMedia(dd["post_path"].to_string()));
+        }
+        sort_carousel(&self.config, &dd["post_path"], &mut media_files);
 
         let mut media_files = media_files
             .into_iter()
             .map(|filename| {
-                Path::new(dd.get("output_path").unwrap())
+                Path::new(&dd["output_path"])
                     .join(filename)
-                    .to_str()
-                    .unwrap()
+                    .to_string_lossy()
                     .to_string()
             })
             .collect::<Vec<String>>();
         //
-        info!("received: {:?}", dd.get("post_path").unwrap());
+        info!("received: {:?}", dd["post_path"]);
         info!("final media_files: {:?}", media_files);
 
         Ok((dd, media_files))
     }
 }
 
-fn find_media_files(post_path: String) -> (Vec<String>, Vec<String>) {
-    const INPUT_DIRECTORY: &str = dotenv!("INPUT_DIRECTORY");
+fn find_media_files(config: &Config, post_path: String) -> (Vec<String>, Vec<String>) {
+    let input_directory = Pattern::escape(&config.input.directory.to_string_lossy());
 
     let mut image_files: Vec<String> = Vec::new();
     let mut video_files: Vec<String> = Vec::new();
     // find the core name of all the post files
-    let post_stem = post_path.split(".").collect::<Vec<&str>>()[0];
+    let post_stem = Pattern::escape(post_path.split('.').next().unwrap_or_default());
 
-    let image_pattern = format!("{}/**/{}*.{}", INPUT_DIRECTORY, post_stem, "jpg");
-    for entry in glob(&image_pattern).expect("Failed to read glob pattern for jpeg") {
+    let image_pattern = format!("{}/**/{}*.{}", input_directory, post_stem, "jpg");
+    for entry in glob(&image_pattern).into_iter().flatten() {
         match entry {
-            Ok(path) => image_files.push(String::from(path.file_name().unwrap().to_str().unwrap())), // only the filename
+            Ok(path) => image_files.extend(
+                path.file_name()
+                    .map(|name| name.to_string_lossy().to_string()),
+            ), // only the filename
             Err(e) => error!("Error: {:?}", e),
         }
     }
 
-    let video_pattern = format!("{}/**/{}*.{}", INPUT_DIRECTORY, post_stem, "mp4");
-    for entry in glob(&video_pattern).expect("Failed to read glob pattern for mp4") {
+    let video_pattern = format!("{}/**/{}*.{}", input_directory, post_stem, "mp4");
+    for entry in glob(&video_pattern).into_iter().flatten() {
         match entry {
-            Ok(path) => video_files.push(String::from(path.file_name().unwrap().to_str().unwrap())), // only the filename
+            Ok(path) => video_files.extend(
+                path.file_name()
+                    .map(|name| name.to_string_lossy().to_string()),
+            ), // only the filename
             Err(e) => error!("Error: {:?}", e),
         }
     }
     (image_files, video_files)
 }
+
+/// Position of an asset in its post's carousel, from Instaloader's `_1`, `_2`, ...
+/// suffix, `None` for a single image or video post.
+pub(crate) fn carousel_index(post_stem: &str, filename: &str) -> Option<usize> {
+    let stem = Path::new(filename).file_stem()?.to_str()?;
+    stem.strip_prefix(post_stem)?
+        .strip_prefix('_')?
+        .parse()
+        .ok()
+}
+
+// put the assets in the order Instagram shows them, glob and the video merge don't keep it
+fn sort_carousel(config: &Config, post_path: &str, media_files: &mut [String]) {
+    let post_stem = post_path.split('.').next().unwrap_or_default();
+    // files named without the number, e.g. by a custom --filename-pattern with {shortcode},
+    // are placed by the order of the sidecar's edges instead
+    let children = metadata::find_metadata(&config.input.directory, post_stem)
+        .map(|metadata| metadata.children)
+        .unwrap_or_default();
+    let edge_index = |filename: &str| {
+        children
+            .iter()
+            .position(|child| {
+                child
+                    .shortcode
+                    .as_ref()
+                    .is_some_and(|shortcode| filename.contains(shortcode.as_str()))
+            })
+            .map(|position| position + 1)
+    };
+
+    // a stable sort, so anything that can't be placed stays in glob order at the end
+    media_files.sort_by_key(|filename| {
+        carousel_index(post_stem, filename)
+            .or_else(|| edge_index(filename))
+            .unwrap_or(usize::MAX)
+    });
+    if !children.is_empty() && children.len() != media_files.len() {
+        debug!(
+            "{} has {} carousel items but {} media files",
+            post_stem,
+            children.len(),
+            media_files.len()
+        );
+    }
+}
+
 fn merge_media_file_lists(a: Vec<String>, b: Vec<String>) -> Vec<String> {
     let b2 = b.clone();
 
@@ -120,6 +180,6 @@ fn merge_media_file_lists(a: Vec<String>, b: Vec<String>) -> Vec<String> {
         .collect();
 
     // merge the cleaned image files with the video files
-    let concat: Vec<String> = cleaned_files.into_iter().chain(b2.into_iter()).collect();
+    let concat: Vec<String> = cleaned_files.into_iter().chain(b2).collect();
     concat
 }
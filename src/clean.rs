@@ -0,0 +1,73 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use glob::{glob, Pattern};
+use log::{error, info};
+
+use crate::{config::Config, manifest::MANIFEST_FILE, output_target};
+
+/// Remove the Markdown posts and images generated by earlier runs.
+///
+/// Only files matching the output target's generated file patterns are touched,
+/// e.g. `YYYY/MM/DD/*_UTC.md` and the `img/instagram` tree for Hexo. Directories
+/// left empty afterwards are removed too. Returns the number of files removed
+/// (or that would be removed on a dry run).
+pub(crate) fn clean_output(config: &Config, dry_run: bool) -> Result<usize, std::io::Error> {
+    let output_directory = &config.output.directory;
+    let target = output_target::from_config(config);
+    let mut removed: Vec<PathBuf> = Vec::new();
+
+    for pattern in target.generated_files() {
+        // a `[` in the directory is part of its name, not a pattern
+        let pattern = format!(
+            "{}/{}",
+            Pattern::escape(&output_directory.to_string_lossy()),
+            pattern
+        );
+        let entries = glob(&pattern).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        for entry in entries {
+            match entry {
+                Ok(path) if path.is_file() => {
+                    if dry_run {
+                        println!("{}", path.display());
+                    } else {
+                        fs::remove_file(&path)?;
+                        info!("removed {}", path.display());
+                    }
+                    removed.push(path);
+                }
+                Ok(_) => {}
+                Err(e) => error!("{:?}", e),
+            }
+        }
+    }
+
+    // without its outputs the manifest would only cause confusion
+    let manifest = output_directory.join(MANIFEST_FILE);
+    if manifest.is_file() && !dry_run {
+        fs::remove_file(&manifest)?;
+    }
+
+    if !dry_run {
+        for path in removed.iter() {
+            remove_empty_parents(output_directory, path)?;
+        }
+    }
+    Ok(removed.len())
+}
+
+// walk up from a removed file, stopping at the output directory or the first non-empty directory
+fn remove_empty_parents(output_directory: &Path, path: &Path) -> Result<(), std::io::Error> {
+    let mut parent = path.parent();
+    while let Some(dir) = parent {
+        if dir == output_directory || !dir.is_dir() || fs::read_dir(dir)?.next().is_some() {
+            break;
+        }
+        fs::remove_dir(dir)?;
+        info!("removed {}", dir.display());
+        parent = dir.parent();
+    }
+    Ok(())
+}
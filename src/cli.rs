@@ -0,0 +1,100 @@
+use std::path::PathBuf;
+
+use chrono::NaiveDate;
+use clap::{Parser, Subcommand};
+use log::LevelFilter;
+
+use crate::output_target::TargetKind;
+
+/// Turn an Instaloader download into Markdown posts and watermarked images for a Hexo site.
+#[derive(Parser, Debug)]
+#[command(version, about)]
+pub(crate) struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Configuration file, defaults to ./instagram.toml when present
+    #[arg(short, long, value_name = "FILE", global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Directory containing the Instaloader download
+    #[arg(short, long, value_name = "DIR", global = true)]
+    pub input_directory: Option<PathBuf>,
+
+    /// Read Instagram's "Download your information" export (zip or directory) instead of an Instaloader download
+    #[arg(short, long, value_name = "PATH", global = true)]
+    pub export: Option<PathBuf>,
+
+    /// Root directory of the site the posts are written into
+    #[arg(short, long, value_name = "DIR", global = true)]
+    pub output_directory: Option<PathBuf>,
+
+    /// Site generator to write posts for
+    #[arg(short, long, value_enum, global = true)]
+    pub target: Option<TargetKind>,
+
+    /// Image used to watermark every photo
+    #[arg(short, long, value_name = "FILE", global = true)]
+    pub watermark: Option<PathBuf>,
+
+    /// Only process posts whose filename starts with PREFIX, e.g. 2023-06
+    #[arg(short, long, value_name = "PREFIX", global = true)]
+    pub filter: Option<String>,
+
+    /// Only process posts made on or after this date (YYYY-MM-DD)
+    #[arg(long, value_name = "DATE", global = true)]
+    pub since: Option<NaiveDate>,
+
+    /// Only process posts made on or before this date (YYYY-MM-DD)
+    #[arg(long, value_name = "DATE", global = true)]
+    pub until: Option<NaiveDate>,
+
+    /// Rebuild everything, ignoring what earlier runs recorded in the manifest
+    #[arg(long, global = true)]
+    pub force: bool,
+
+    /// Log more, repeat for debug and trace output
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    pub verbose: u8,
+
+    /// Log nothing but errors, repeat to silence errors too
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    pub quiet: u8,
+}
+
+#[derive(Subcommand, Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Command {
+    /// List the posts found in the input directory
+    Scan,
+    /// Find, resize, thumbnail and watermark the media of every post
+    Media,
+    /// Render the Markdown for every post without touching the media
+    Render,
+    /// Process media and render posts (the default)
+    Build,
+    /// Remove generated Markdown and images from the output directory
+    Clean {
+        /// Only list what would be removed
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+impl Cli {
+    /// The subcommand to run, `build` when none was given.
+    pub(crate) fn command(&self) -> Command {
+        self.command.unwrap_or(Command::Build)
+    }
+
+    /// Log level from the -v/-q flags, used when RUST_LOG is not set.
+    pub(crate) fn log_level(&self) -> LevelFilter {
+        match self.verbose as i16 - self.quiet as i16 {
+            i16::MIN..=-2 => LevelFilter::Off,
+            -1 => LevelFilter::Error,
+            0 => LevelFilter::Warn,
+            1 => LevelFilter::Info,
+            2 => LevelFilter::Debug,
+            _ => LevelFilter::Trace,
+        }
+    }
+}
@@ -0,0 +1,783 @@
+use std::{
+    env, fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use chrono::NaiveDate;
+use log::debug;
+use serde::{de::DeserializeOwned, Deserialize};
+
+use crate::cli::{Cli, Command};
+use crate::output_target::TargetKind;
+
+/// The configuration file read when no `--config` flag is given.
+pub(crate) const DEFAULT_CONFIG_FILE: &str = "instagram.toml";
+
+/// Runtime configuration, built once in `main` and shared with every actor.
+///
+/// Values are layered: built-in defaults, then the TOML file, then environment
+/// variables (including anything in `.env`), then command-line flags.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Config {
+    pub input: InputConfig,
+    pub output: OutputConfig,
+    pub images: ImageConfig,
+    pub watermark: WatermarkConfig,
+    pub exif: ExifConfig,
+    pub duplicates: DuplicatesConfig,
+    pub alt: AltConfig,
+    pub captions: CaptionConfig,
+    pub tags: TagsConfig,
+    pub titles: TitlesConfig,
+    pub slugs: SlugsConfig,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct InputConfig {
+    /// Directory containing the Instaloader download.
+    pub directory: PathBuf,
+    /// Only posts whose filename starts with this prefix are processed, e.g. `2023`.
+    pub filter: String,
+    /// Only posts made on or after this date are processed.
+    pub since: Option<NaiveDate>,
+    /// Only posts made on or before this date are processed.
+    pub until: Option<NaiveDate>,
+    /// Instagram "Download your information" export (zip or extracted directory)
+    /// to unpack into `directory` before processing, instead of using Instaloader.
+    pub export: Option<PathBuf>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct OutputConfig {
+    /// Root of the site source, markdown and images are written below it.
+    pub directory: PathBuf,
+    /// Glob of the Tera templates used to render posts.
+    pub templates: String,
+    /// Name of the template each post is rendered with.
+    pub template: String,
+    /// The site generator the posts are written for.
+    pub target: TargetKind,
+    /// Content section for the targets that use one (Hugo, Zola, Eleventy).
+    pub section: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct ImageConfig {
+    /// Box the large rendition is scaled down to fit within, keeping its aspect ratio.
+    pub large: Dimensions,
+    pub thumbnail: Dimensions,
+    /// How the part of the image that becomes the thumbnail is chosen.
+    pub thumbnail_crop: ThumbnailCrop,
+    /// Widths of the narrower renditions made for `srcset`, in addition to the large image.
+    pub widths: Vec<u32>,
+    /// JPEG quality of the generated images, 1-100.
+    pub quality: u8,
+    /// Extra encodings written next to every JPEG, for `<picture>` sources.
+    pub formats: Vec<ImageFormat>,
+    /// AVIF quality, 1-100. WebP is always encoded lossless.
+    pub avif_quality: u8,
+    /// AVIF encoder speed, 1 (slowest, smallest) to 10.
+    pub avif_speed: u8,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ImageFormat {
+    /// Lossless, the pure Rust encoders have no lossy mode, so a photo is usually
+    /// several times the size of its JPEG.
+    Webp,
+    Avif,
+}
+
+impl ImageFormat {
+    pub(crate) fn extension(&self) -> &'static str {
+        match self {
+            ImageFormat::Webp => "webp",
+            ImageFormat::Avif => "avif",
+        }
+    }
+
+    pub(crate) fn mime_type(&self) -> &'static str {
+        match self {
+            ImageFormat::Webp => "image/webp",
+            ImageFormat::Avif => "image/avif",
+        }
+    }
+}
+
+/// Thumbnails are cropped to the thumbnail's aspect ratio before they're scaled down.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ThumbnailCrop {
+    /// The middle of the image.
+    #[default]
+    Center,
+    /// The busiest part of the image, trimming the edges with the least detail.
+    Entropy,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Dimensions {
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct WatermarkConfig {
+    /// Image stamped on every photo, unless `text` is set.
+    pub image: PathBuf,
+    /// Text stamped instead of the image, e.g. "© koothooloo 2026".
+    pub text: Option<String>,
+    /// TrueType font the text is rendered with.
+    pub font: Option<PathBuf>,
+    /// Colour of the text, `#rrggbb`.
+    pub color: String,
+    pub anchor: Anchor,
+    /// Distance from the edges as a percentage of the image width.
+    pub margin: f32,
+    /// Width of the watermark as a fraction of the image width. Unset, an image
+    /// watermark keeps its own size and text spans a quarter of the image.
+    pub scale: Option<f32>,
+    /// 0 (invisible) to 1.
+    pub opacity: f32,
+    /// Images smaller than this in either direction aren't watermarked.
+    pub minimum: Dimensions,
+    /// Checked in order, the first rule matching a post decides its watermark.
+    pub rules: Vec<WatermarkRule>,
+    /// TOML file of per-post changes keyed by post stem (or asset stem for a
+    /// single carousel item), taking precedence over the rules.
+    pub overrides: Option<PathBuf>,
+}
+
+/// What EXIF the output JPEGs carry. The defaults strip all of it.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct ExifConfig {
+    /// Tags copied from the original, by group (`camera`, `lens`, `exposure`,
+    /// `date`, `copyright`) or tag name (e.g. `LensModel`). GPS is never copied.
+    pub keep: Vec<String>,
+    /// Written into every output JPEG, replacing the original's.
+    pub artist: Option<String>,
+    pub copyright: Option<String>,
+}
+
+/// Alt text for images without an accessibility caption from Instagram.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct AltConfig {
+    /// Tera template with `sentence` (the caption's first sentence, without
+    /// hashtags), `index`, `count` and `media_type`.
+    pub template: String,
+    /// TOML file of alt text keyed by asset stem, taking precedence over Instagram's.
+    pub overrides: Option<PathBuf>,
+}
+
+/// How hashtags appear in the caption of a post, they are its tags either way.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct CaptionConfig {
+    /// Link every hashtag to its tag page.
+    pub link_hashtags: bool,
+    /// URL of a tag page, `{tag}` is replaced with the tag.
+    pub tag_url: String,
+    /// Leave out the block of hashtags that ends many captions.
+    pub strip_trailing_hashtags: bool,
+}
+
+/// Turning hashtags into the tags and categories of a post.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct TagsConfig {
+    /// TOML file of case folding, aliases, a blocklist and categories, see
+    /// `tags::TagMapping`. Without it the hashtags are the tags as they are.
+    pub mapping: Option<PathBuf>,
+}
+
+/// Where the title of a post comes from.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct TitlesConfig {
+    pub strategy: TitleStrategy,
+    /// Tera template of the whole title for the `template` strategy, with `tags`,
+    /// `sentence` (the caption's first sentence, without hashtags), `location` and `date`.
+    pub template: String,
+}
+
+/// What the title starts with, followed by the date. A post without it falls back
+/// on its first hashtag, then on the date alone.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum TitleStrategy {
+    /// The first hashtag.
+    #[default]
+    Hashtag,
+    /// The caption's first sentence.
+    Sentence,
+    /// The name of the location the post is tagged with.
+    Location,
+    /// `titles.template`, which is the whole title.
+    Template,
+}
+
+/// Turning a title into the slug of the post's filename and permalink.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct SlugsConfig {
+    /// Longest slug, in characters, cut at a word boundary.
+    pub max_length: usize,
+    /// Words left out of slugs, unless the slug would be empty without them.
+    pub stop_words: Vec<String>,
+}
+
+/// Finding the same photo in several posts, e.g. a repost or a post Instaloader
+/// downloaded again under a new timestamp after it was edited.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct DuplicatesConfig {
+    pub mode: DuplicateMode,
+    /// How many of the 64 bits of two images' perceptual hashes may differ for
+    /// them to count as near-duplicates.
+    pub threshold: u32,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum DuplicateMode {
+    /// Images aren't compared.
+    #[default]
+    Off,
+    /// Near-duplicates are logged.
+    Report,
+    /// Files identical to an earlier post's, byte for byte, link to the output of the
+    /// earliest post with them instead of being processed again.
+    Reuse,
+    /// Posts whose images are all near-duplicates of an earlier post's are left out.
+    Skip,
+}
+
+/// Skip or swap the watermark for the posts with a hashtag and/or in a date range.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct WatermarkRule {
+    /// Matches posts with this hashtag, with or without the `#`, ignoring case.
+    pub hashtag: Option<String>,
+    pub since: Option<NaiveDate>,
+    pub until: Option<NaiveDate>,
+    pub skip: bool,
+    pub image: Option<PathBuf>,
+    pub text: Option<String>,
+}
+
+/// What a rule or override does to the watermark: leave it off, or use another image or text.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct WatermarkOverride {
+    pub skip: bool,
+    pub image: Option<PathBuf>,
+    pub text: Option<String>,
+}
+
+impl WatermarkRule {
+    pub(crate) fn change(&self) -> WatermarkOverride {
+        WatermarkOverride {
+            skip: self.skip,
+            image: self.image.clone(),
+            text: self.text.clone(),
+        }
+    }
+}
+
+/// Where on the image the watermark goes.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub(crate) enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    #[default]
+    BottomRight,
+}
+
+impl Default for InputConfig {
+    fn default() -> Self {
+        InputConfig {
+            directory: PathBuf::from("koothooloo"),
+            filter: String::new(),
+            since: None,
+            until: None,
+            export: None,
+        }
+    }
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        OutputConfig {
+            directory: PathBuf::from("output"),
+            templates: String::from("templates/**/*.md"),
+            template: String::from("001_post.md"),
+            target: TargetKind::default(),
+            section: String::from("instagram"),
+        }
+    }
+}
+
+impl Default for ImageConfig {
+    fn default() -> Self {
+        ImageConfig {
+            large: Dimensions {
+                width: 2048,
+                height: 2048,
+            },
+            thumbnail: Dimensions {
+                width: 300,
+                height: 300,
+            },
+            thumbnail_crop: ThumbnailCrop::default(),
+            widths: vec![480, 960, 1440],
+            quality: 85,
+            formats: Vec::new(),
+            avif_quality: 60,
+            avif_speed: 6,
+        }
+    }
+}
+
+impl Default for AltConfig {
+    fn default() -> Self {
+        AltConfig {
+            template: String::from(
+                "{{ sentence }}{% if count > 1 %} ({{ media_type }} {{ index }} of {{ count }}){% endif %}",
+            ),
+            overrides: None,
+        }
+    }
+}
+
+impl Default for CaptionConfig {
+    fn default() -> Self {
+        CaptionConfig {
+            link_hashtags: false,
+            tag_url: String::from("/tags/{tag}/"),
+            strip_trailing_hashtags: false,
+        }
+    }
+}
+
+impl Default for TitlesConfig {
+    fn default() -> Self {
+        TitlesConfig {
+            strategy: TitleStrategy::default(),
+            template: String::from("{{ sentence }}"),
+        }
+    }
+}
+
+impl Default for SlugsConfig {
+    fn default() -> Self {
+        SlugsConfig {
+            max_length: 60,
+            stop_words: [
+                "a", "an", "and", "at", "by", "for", "from", "in", "is", "of", "on", "or", "the",
+                "to", "with",
+            ]
+            .iter()
+            .map(|word| word.to_string())
+            .collect(),
+        }
+    }
+}
+
+impl Default for DuplicatesConfig {
+    fn default() -> Self {
+        DuplicatesConfig {
+            mode: DuplicateMode::default(),
+            threshold: 6,
+        }
+    }
+}
+
+impl Default for WatermarkConfig {
+    fn default() -> Self {
+        WatermarkConfig {
+            image: PathBuf::from("watermark.png"),
+            text: None,
+            font: None,
+            color: String::from("#ffffff"),
+            anchor: Anchor::default(),
+            margin: 1.5,
+            scale: None,
+            opacity: 1.0,
+            minimum: Dimensions {
+                width: 0,
+                height: 0,
+            },
+            rules: Vec::new(),
+            overrides: None,
+        }
+    }
+}
+
+impl WatermarkConfig {
+    /// The text colour as RGB, `None` when it isn't a `#rrggbb` colour.
+    pub(crate) fn rgb(&self) -> Option<[u8; 3]> {
+        let hex = self.color.strip_prefix('#')?;
+        if hex.len() != 6 || !hex.is_ascii() {
+            return None;
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+        Some([channel(0)?, channel(2)?, channel(4)?])
+    }
+}
+
+/// Read a TOML file, the configuration itself or one the configuration points at.
+pub(crate) fn read_toml<T: DeserializeOwned>(path: &Path) -> Result<T, ConfigError> {
+    let contents =
+        fs::read_to_string(path).map_err(|e| ConfigError::Read(path.to_path_buf(), e))?;
+    toml::from_str(&contents).map_err(|e| ConfigError::Parse(path.to_path_buf(), e))
+}
+
+#[derive(Debug)]
+pub(crate) enum ConfigError {
+    Read(PathBuf, std::io::Error),
+    Parse(PathBuf, toml::de::Error),
+    Env(String, String),
+    Invalid(Vec<String>),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Read(path, e) => write!(f, "unable to read {}: {}", path.display(), e),
+            ConfigError::Parse(path, e) => write!(f, "unable to parse {}: {}", path.display(), e),
+            ConfigError::Env(key, value) => write!(f, "invalid value {:?} for {}", value, key),
+            ConfigError::Invalid(problems) => {
+                writeln!(f, "invalid configuration:")?;
+                for problem in problems {
+                    writeln!(f, "  - {}", problem)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl Config {
+    /// Load the configuration file, apply environment and command-line overrides and validate the result.
+    pub(crate) fn load(cli: &Cli) -> Result<Config, ConfigError> {
+        let mut config = match &cli.config {
+            Some(path) => Config::from_file(path)?,
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => {
+                Config::from_file(Path::new(DEFAULT_CONFIG_FILE))?
+            }
+            None => Config::default(),
+        };
+        config.apply_env()?;
+        config.apply_cli(cli);
+        config.validate(cli.command())?;
+        debug!("config: {:?}", config);
+        Ok(config)
+    }
+
+    fn from_file(path: &Path) -> Result<Config, ConfigError> {
+        read_toml(path)
+    }
+
+    // the variable names are the ones the old compile-time .env file used
+    fn apply_env(&mut self) -> Result<(), ConfigError> {
+        if let Ok(value) = env::var("INPUT_DIRECTORY") {
+            self.input.directory = PathBuf::from(value);
+        }
+        if let Ok(value) = env::var("TEST_INPUT") {
+            self.input.filter = value;
+        }
+        if let Ok(value) = env::var("OUTPUT_DIRECTORY") {
+            self.output.directory = PathBuf::from(value);
+        }
+        if let Ok(value) = env::var("WATERMARK_IMG") {
+            self.watermark.image = PathBuf::from(value);
+        }
+        env_u32("LARGE_IMAGE_DIMENSIONS_WIDTH", &mut self.images.large.width)?;
+        env_u32(
+            "LARGE_IMAGE_DIMENSIONS_HEIGHT",
+            &mut self.images.large.height,
+        )?;
+        env_u32(
+            "THUMBNAIL_IMAGE_DIMENSIONS_WIDTH",
+            &mut self.images.thumbnail.width,
+        )?;
+        env_u32(
+            "THUMBNAIL_IMAGE_DIMENSIONS_HEIGHT",
+            &mut self.images.thumbnail.height,
+        )?;
+        Ok(())
+    }
+
+    fn apply_cli(&mut self, cli: &Cli) {
+        if let Some(directory) = &cli.input_directory {
+            self.input.directory = directory.clone();
+        }
+        if let Some(directory) = &cli.output_directory {
+            self.output.directory = directory.clone();
+        }
+        if let Some(target) = cli.target {
+            self.output.target = target;
+        }
+        if let Some(watermark) = &cli.watermark {
+            self.watermark.image = watermark.clone();
+        }
+        if let Some(filter) = &cli.filter {
+            self.input.filter = filter.clone();
+        }
+        if cli.since.is_some() {
+            self.input.since = cli.since;
+        }
+        if cli.until.is_some() {
+            self.input.until = cli.until;
+        }
+        if let Some(export) = &cli.export {
+            self.input.export = Some(export.clone());
+        }
+    }
+
+    // only what the command uses is checked, e.g. `render` never reads the watermark
+    fn validate(&self, command: Command) -> Result<(), ConfigError> {
+        let mut problems: Vec<String> = Vec::new();
+
+        let reads_input = !matches!(command, Command::Clean { .. });
+        let processes_media = matches!(command, Command::Media | Command::Build);
+        let renders_posts = matches!(command, Command::Render | Command::Build);
+
+        if reads_input {
+            match &self.input.export {
+                // the export is unpacked into the input directory, which is created if needed
+                Some(export) if !export.exists() => {
+                    problems.push(format!("export {} does not exist", export.display()));
+                }
+                Some(_) => {}
+                None if !self.input.directory.is_dir() => {
+                    problems.push(format!(
+                        "input directory {} does not exist",
+                        self.input.directory.display()
+                    ));
+                }
+                None => {}
+            }
+            if let (Some(since), Some(until)) = (self.input.since, self.input.until) {
+                if since > until {
+                    problems.push(format!(
+                        "input.since {} is after input.until {}",
+                        since, until
+                    ));
+                }
+            }
+        }
+        if processes_media {
+            match (&self.watermark.text, &self.watermark.font) {
+                (None, _) if !self.watermark.image.is_file() => {
+                    problems.push(format!(
+                        "watermark image {} does not exist",
+                        self.watermark.image.display()
+                    ));
+                }
+                (Some(_), None) => {
+                    problems.push(String::from("watermark.text needs a watermark.font"));
+                }
+                (Some(_), Some(font)) if !font.is_file() => {
+                    problems.push(format!("watermark font {} does not exist", font.display()));
+                }
+                _ => {}
+            }
+            if self.watermark.rgb().is_none() {
+                problems.push(String::from("watermark.color must look like #rrggbb"));
+            }
+            if !(0.0..=50.0).contains(&self.watermark.margin) {
+                problems.push(String::from("watermark.margin must be between 0 and 50"));
+            }
+            if self
+                .watermark
+                .scale
+                .is_some_and(|scale| !(scale > 0.0 && scale <= 1.0))
+            {
+                problems.push(String::from("watermark.scale must be between 0 and 1"));
+            }
+            if !(0.0..=1.0).contains(&self.watermark.opacity) {
+                problems.push(String::from("watermark.opacity must be between 0 and 1"));
+            }
+            for (index, rule) in self.watermark.rules.iter().enumerate() {
+                let name = format!("watermark.rules[{}]", index);
+                if rule.hashtag.is_none() && rule.since.is_none() && rule.until.is_none() {
+                    problems.push(format!("{} needs a hashtag, since or until", name));
+                }
+                problems.extend(override_problems(&name, &rule.change(), self));
+            }
+            if let Some(overrides) = &self.watermark.overrides {
+                match crate::watermark::read_overrides(overrides) {
+                    Ok(overrides) => {
+                        for (stem, change) in overrides.iter() {
+                            let name = format!("watermark override {}", stem);
+                            problems.extend(override_problems(&name, change, self));
+                        }
+                    }
+                    Err(e) => problems.push(e.to_string()),
+                }
+            }
+        }
+        for (name, dimensions) in [
+            ("images.large", self.images.large),
+            ("images.thumbnail", self.images.thumbnail),
+        ] {
+            if dimensions.width == 0 || dimensions.height == 0 {
+                problems.push(format!("{} dimensions must be greater than zero", name));
+            }
+        }
+        if self.images.widths.contains(&0) {
+            problems.push(String::from("images.widths must be greater than zero"));
+        }
+        if !(1..=100).contains(&self.images.quality) {
+            problems.push(String::from("images.quality must be between 1 and 100"));
+        }
+        if !(1..=100).contains(&self.images.avif_quality) {
+            problems.push(String::from(
+                "images.avif_quality must be between 1 and 100",
+            ));
+        }
+        if !(1..=10).contains(&self.images.avif_speed) {
+            problems.push(String::from("images.avif_speed must be between 1 and 10"));
+        }
+        if renders_posts {
+            if let Err(e) = tera::Tera::default().add_raw_template("alt", &self.alt.template) {
+                problems.push(format!("alt.template is not a valid template: {}", e));
+            }
+            if let Some(overrides) = &self.alt.overrides {
+                if let Err(e) = crate::alt_text::read_overrides(overrides) {
+                    problems.push(e.to_string());
+                }
+            }
+            if self.captions.link_hashtags && !self.captions.tag_url.contains("{tag}") {
+                problems.push(String::from("captions.tag_url must contain {tag}"));
+            }
+            if self.titles.strategy == TitleStrategy::Template {
+                if let Err(e) =
+                    tera::Tera::default().add_raw_template("title", &self.titles.template)
+                {
+                    problems.push(format!("titles.template is not a valid template: {}", e));
+                }
+            }
+            // room for a word and a collision suffix
+            if self.slugs.max_length < 8 {
+                problems.push(String::from("slugs.max_length must be at least 8"));
+            }
+            if let Some(mapping) = &self.tags.mapping {
+                if let Err(e) = crate::tags::read_mapping(mapping) {
+                    problems.push(e.to_string());
+                }
+            }
+        }
+        if self.duplicates.threshold > 64 {
+            problems.push(String::from(
+                "duplicates.threshold must be between 0 and 64",
+            ));
+        }
+        for name in self.exif.keep.iter() {
+            if !crate::exif_data::is_keepable(name) {
+                problems.push(format!(
+                    "exif.keep {} is not a group or tag that can be kept",
+                    name
+                ));
+            }
+        }
+        if self.output.section.is_empty() || self.output.section.contains(['/', '\\']) {
+            problems.push(String::from(
+                "output.section must be a single directory name",
+            ));
+        }
+        if let Err(e) = glob::Pattern::new(&self.output.templates) {
+            problems.push(format!("output.templates is not a valid glob: {}", e));
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError::Invalid(problems))
+        }
+    }
+}
+
+fn override_problems(name: &str, change: &WatermarkOverride, config: &Config) -> Vec<String> {
+    let mut problems: Vec<String> = Vec::new();
+    if let Some(image) = change.image.as_ref().filter(|image| !image.is_file()) {
+        problems.push(format!("{} image {} does not exist", name, image.display()));
+    }
+    if change.text.is_some() && config.watermark.font.is_none() {
+        problems.push(format!("{} text needs a watermark.font", name));
+    }
+    problems
+}
+
+fn env_u32(key: &str, target: &mut u32) -> Result<(), ConfigError> {
+    if let Ok(value) = env::var(key) {
+        *target = value
+            .parse()
+            .map_err(|_| ConfigError::Env(key.to_string(), value))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn problems(config: &Config, command: Command) -> Vec<String> {
+        match config.validate(command) {
+            Ok(()) => Vec::new(),
+            Err(ConfigError::Invalid(problems)) => problems,
+            Err(e) => panic!("unexpected {}", e),
+        }
+    }
+
+    #[test]
+    fn clean_needs_no_input_or_watermark() {
+        let config = Config::default();
+        assert!(problems(&config, Command::Clean { dry_run: false }).is_empty());
+        assert!(problems(&config, Command::Scan)[0].starts_with("input directory"));
+    }
+
+    #[test]
+    fn only_media_commands_check_the_watermark() {
+        let mut config = Config::default();
+        config.input.directory = PathBuf::from("src");
+        config.watermark.image = PathBuf::from("missing.png");
+        assert!(problems(&config, Command::Scan).is_empty());
+        assert!(problems(&config, Command::Render).is_empty());
+        for command in [Command::Media, Command::Build] {
+            assert_eq!(
+                problems(&config, command),
+                ["watermark image missing.png does not exist"]
+            );
+        }
+    }
+
+    #[test]
+    fn only_rendering_commands_check_the_templates() {
+        let mut config = Config::default();
+        config.input.directory = PathBuf::from("src");
+        config.watermark.image = PathBuf::from("Cargo.toml");
+        config.alt.template = String::from("{{ unclosed");
+        assert!(problems(&config, Command::Media).is_empty());
+        assert!(problems(&config, Command::Render)[0].starts_with("alt.template"));
+    }
+}
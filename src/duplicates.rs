@@ -0,0 +1,263 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use image::imageops::FilterType;
+use log::{debug, info, warn};
+use rayon::prelude::*;
+
+use crate::{
+    config::{Config, DuplicateMode},
+    manifest::{self, Fingerprint, Manifest},
+    output_target, post_actor, video,
+};
+
+/// What the duplicate pass decided for the posts of this run.
+#[derive(Debug, Default)]
+pub(crate) struct Duplicates {
+    /// Assets that aren't processed, mapped to the earlier asset whose output they link to.
+    pub reused: HashMap<String, String>,
+    /// Posts that are left out, by caption filename.
+    pub skipped: HashSet<String>,
+}
+
+/// Compare the images of every post with those of the posts before it.
+///
+/// `posts` pairs each caption filename with the media filenames of its post.
+pub(crate) fn find(
+    config: &Config,
+    manifest: &mut Manifest,
+    posts: &[(String, Vec<String>)],
+) -> Duplicates {
+    let mut duplicates = Duplicates::default();
+    if config.duplicates.mode == DuplicateMode::Off {
+        return duplicates;
+    }
+    let hashes = fingerprints(config, manifest, posts);
+    let can_reuse = config.duplicates.mode == DuplicateMode::Reuse
+        && output_target::from_config(config).shares_assets();
+
+    let mut earlier: Vec<(&str, &str, &Fingerprint)> = Vec::new();
+    for (post, assets) in post_actor::in_date_order(posts, |(post, _)| post) {
+        let post_stem = post_actor::post_stem_from_asset(post);
+        let mut all_duplicates = !assets.is_empty();
+        for asset in assets {
+            let Some(fingerprint) = hashes.get(asset.as_str()) else {
+                all_duplicates = false;
+                continue;
+            };
+            let closest = earlier
+                .iter()
+                .filter(|(other_stem, _, _)| *other_stem != post_stem)
+                .map(|(_, other, other_fingerprint)| {
+                    let distance = (fingerprint.hash ^ other_fingerprint.hash).count_ones();
+                    (*other, *other_fingerprint, distance)
+                })
+                .min_by_key(|(_, _, distance)| *distance);
+            match closest {
+                Some((other, other_fingerprint, distance))
+                    if distance <= config.duplicates.threshold =>
+                {
+                    warn!(
+                        "{} looks like a duplicate of {} ({} of 64 bits differ)",
+                        asset, other, distance
+                    );
+                    // a re-edit or a flat frame can hash the same, only the same file is reused
+                    if can_reuse && fingerprint.content_hash == other_fingerprint.content_hash {
+                        // link to the asset that is processed, not another copy of it
+                        let original = duplicates.reused.get(other).cloned();
+                        let original = original.unwrap_or_else(|| other.to_string());
+                        duplicates.reused.insert(asset.clone(), original);
+                    }
+                }
+                _ => all_duplicates = false,
+            }
+            earlier.push((post_stem, asset, fingerprint));
+        }
+        if config.duplicates.mode == DuplicateMode::Skip && all_duplicates {
+            info!("Skipping {}, all of its images were posted before", post);
+            duplicates.skipped.insert(post.clone());
+        }
+    }
+    duplicates
+}
+
+// perceptual hash of every image, and every video's poster, and the content hash of
+// every asset, from the manifest when unchanged
+fn fingerprints(
+    config: &Config,
+    manifest: &mut Manifest,
+    posts: &[(String, Vec<String>)],
+) -> HashMap<String, Fingerprint> {
+    let sources: Vec<(String, PathBuf, PathBuf, String)> = posts
+        .iter()
+        .flat_map(|(_, assets)| assets)
+        .filter_map(|asset| {
+            let path = config.input.directory.join(asset);
+            let source = match video::is_video(asset) {
+                true => video::sidecar_poster(&config.input.directory, asset)?,
+                false => path.clone(),
+            };
+            let input_hash = manifest::hash_inputs(&[path.clone(), source.clone()], &[]);
+            Some((asset.clone(), path, source, input_hash))
+        })
+        .collect();
+
+    let cached = &manifest.fingerprints;
+    let computed: Vec<(String, Fingerprint)> = sources
+        .into_par_iter()
+        .filter_map(|(asset, path, source, input_hash)| {
+            if let Some(fingerprint) = cached.get(&asset).filter(|fingerprint| {
+                fingerprint.input_hash == input_hash && !fingerprint.content_hash.is_empty()
+            }) {
+                return Some((asset, fingerprint.clone()));
+            }
+            let content_hash = match manifest::hash_contents(&path) {
+                Ok(content_hash) => content_hash,
+                Err(e) => {
+                    debug!("{}: unable to read: {}", path.display(), e);
+                    return None;
+                }
+            };
+            match difference_hash(&source) {
+                Ok(hash) => {
+                    debug!("{}: perceptual hash {:016x}", asset, hash);
+                    let fingerprint = Fingerprint {
+                        input_hash,
+                        hash,
+                        content_hash,
+                    };
+                    Some((asset, fingerprint))
+                }
+                Err(e) => {
+                    // the media stage reports the broken image
+                    debug!("{}: unable to hash: {}", source.display(), e);
+                    None
+                }
+            }
+        })
+        .collect();
+
+    let mut hashes = HashMap::new();
+    for (asset, fingerprint) in computed {
+        manifest
+            .fingerprints
+            .insert(asset.clone(), fingerprint.clone());
+        hashes.insert(asset, fingerprint);
+    }
+    hashes
+}
+
+// dHash: whether each pixel of a 9x8 greyscale copy is brighter than its right neighbour,
+// which survives resizing, recompression and small edits
+fn difference_hash(path: &Path) -> Result<u64, image::ImageError> {
+    let grey = image::open(path)?
+        .resize_exact(9, 8, FilterType::Triangle)
+        .to_luma8();
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            let brighter = grey.get_pixel(x, y)[0] > grey.get_pixel(x + 1, y)[0];
+            hash = (hash << 1) | brighter as u64;
+        }
+    }
+    Ok(hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    const POSTS: [&str; 3] = [
+        "2023-01-01_10-00-00_UTC",
+        "2023-02-01_10-00-00_UTC",
+        "2023-03-01_10-00-00_UTC",
+    ];
+
+    fn gradient() -> RgbImage {
+        RgbImage::from_fn(90, 80, |x, y| {
+            let level = ((x * 3 + y) % 256) as u8;
+            Rgb([level, level / 2, 255 - level])
+        })
+    }
+
+    // the first post's photo, the same file again and the photo saved as a JPEG
+    fn find_in(name: &str, mode: DuplicateMode) -> (Duplicates, Vec<u64>) {
+        let directory =
+            std::env::temp_dir().join(format!("vv-duplicates-{}-{}", std::process::id(), name));
+        std::fs::create_dir_all(&directory).unwrap();
+        let assets = [
+            format!("{}.png", POSTS[0]),
+            format!("{}.png", POSTS[1]),
+            format!("{}.jpg", POSTS[2]),
+        ];
+        gradient().save(directory.join(&assets[0])).unwrap();
+        std::fs::copy(directory.join(&assets[0]), directory.join(&assets[1])).unwrap();
+        gradient().save(directory.join(&assets[2])).unwrap();
+
+        let mut config = Config::default();
+        config.input.directory = directory.clone();
+        config.duplicates.mode = mode;
+        config.duplicates.threshold = 4;
+        let posts: Vec<(String, Vec<String>)> = POSTS
+            .iter()
+            .zip(assets.iter())
+            .map(|(post, asset)| (format!("{}.txt", post), vec![asset.clone()]))
+            .collect();
+        let mut manifest = Manifest::default();
+        let duplicates = find(&config, &mut manifest, &posts);
+        let hashes = assets
+            .iter()
+            .map(|asset| manifest.fingerprints[asset].hash)
+            .collect();
+        std::fs::remove_dir_all(&directory).unwrap();
+        (duplicates, hashes)
+    }
+
+    #[test]
+    fn only_identical_files_are_reused() {
+        let (duplicates, hashes) = find_in("reuse", DuplicateMode::Reuse);
+        assert_eq!(
+            duplicates.reused,
+            HashMap::from([(format!("{}.png", POSTS[1]), format!("{}.png", POSTS[0]))])
+        );
+        assert!(duplicates.skipped.is_empty());
+        // the JPEG is a near-duplicate all the same
+        assert!((hashes[0] ^ hashes[2]).count_ones() <= 4);
+    }
+
+    #[test]
+    fn near_duplicates_are_skipped() {
+        let (duplicates, _) = find_in("skip", DuplicateMode::Skip);
+        assert!(duplicates.reused.is_empty());
+        let skipped: HashSet<String> = POSTS[1..]
+            .iter()
+            .map(|post| format!("{}.txt", post))
+            .collect();
+        assert_eq!(duplicates.skipped, skipped);
+    }
+
+    #[test]
+    fn nothing_is_compared_when_off() {
+        let mut manifest = Manifest::default();
+        let posts = vec![(String::from("a.txt"), vec![String::from("a.jpg")])];
+        let duplicates = find(&Config::default(), &mut manifest, &posts);
+        assert!(duplicates.reused.is_empty() && duplicates.skipped.is_empty());
+        assert!(manifest.fingerprints.is_empty());
+    }
+
+    #[test]
+    fn difference_hash_of_different_images() {
+        let path = std::env::temp_dir().join(format!("vv-dhash-{}.png", std::process::id()));
+        gradient().save(&path).unwrap();
+        let hash = difference_hash(&path).unwrap();
+        image::imageops::flip_horizontal(&gradient())
+            .save(&path)
+            .unwrap();
+        let flipped = difference_hash(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!((hash ^ flipped).count_ones() > 16);
+    }
+}
@@ -0,0 +1,76 @@
+use std::{fmt, path::PathBuf};
+
+use actix::MailboxError;
+
+use crate::config::ConfigError;
+
+/// Everything that can go wrong while building a post or one of its assets.
+///
+/// Actors return these instead of panicking, so a single odd file only fails
+/// its own post or asset and shows up in the report at the end of the run.
+#[derive(Debug)]
+pub(crate) enum Error {
+    Io(PathBuf, std::io::Error),
+    /// A filename without Instaloader's `YYYY-MM-DD_HH-MM-SS_UTC` date.
+    InvalidPostName(String),
+    /// A post that was found without any images or videos.
+    NoMedia(String),
+    Image(PathBuf, String),
+    Template(String),
+    Export(String),
+    Config(ConfigError),
+    /// An actor went away before answering.
+    Mailbox(MailboxError),
+}
+
+impl Error {
+    /// Attach the path being read or written to an I/O error.
+    pub(crate) fn io(path: impl Into<PathBuf>) -> impl FnOnce(std::io::Error) -> Error {
+        let path = path.into();
+        move |e| Error::Io(path, e)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            Error::InvalidPostName(name) => {
+                write!(f, "{} is not named like YYYY-MM-DD_HH-MM-SS_UTC", name)
+            }
+            Error::NoMedia(post) => write!(f, "{} has no images or videos", post),
+            Error::Image(path, message) => write!(f, "{}: {}", path.display(), message),
+            Error::Template(message) => write!(f, "template error: {}", message),
+            Error::Export(message) => write!(f, "export: {}", message),
+            Error::Config(e) => write!(f, "{}", e),
+            Error::Mailbox(e) => write!(f, "actor failed to respond: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<ConfigError> for Error {
+    fn from(e: ConfigError) -> Self {
+        Error::Config(e)
+    }
+}
+
+impl From<MailboxError> for Error {
+    fn from(e: MailboxError) -> Self {
+        Error::Mailbox(e)
+    }
+}
+
+impl From<tera::Error> for Error {
+    // tera keeps the useful part of the message in the error's source chain
+    fn from(e: tera::Error) -> Self {
+        let mut message = e.to_string();
+        let mut source = std::error::Error::source(&e);
+        while let Some(cause) = source {
+            message.push_str(&format!(": {}", cause));
+            source = cause.source();
+        }
+        Error::Template(message)
+    }
+}
@@ -0,0 +1,163 @@
+use std::{fs::File, io::BufReader, path::Path};
+
+use exif::{experimental::Writer, Exif, Field, In, Reader, Tag, Value};
+use log::debug;
+use serde::Serialize;
+
+use crate::{config::ExifConfig, error::Error};
+
+// the tags that can be copied into the output images, by the group that keeps them,
+// GPS positions and serial numbers are deliberately not among them
+const KEEPABLE: &[(&str, Tag)] = &[
+    ("camera", Tag::Make),
+    ("camera", Tag::Model),
+    ("camera", Tag::Software),
+    ("lens", Tag::LensMake),
+    ("lens", Tag::LensModel),
+    ("lens", Tag::FocalLength),
+    ("lens", Tag::FocalLengthIn35mmFilm),
+    ("exposure", Tag::ExposureTime),
+    ("exposure", Tag::FNumber),
+    ("exposure", Tag::PhotographicSensitivity),
+    ("exposure", Tag::ExposureBiasValue),
+    ("exposure", Tag::ExposureProgram),
+    ("exposure", Tag::MeteringMode),
+    ("exposure", Tag::Flash),
+    ("exposure", Tag::WhiteBalance),
+    ("date", Tag::DateTimeOriginal),
+    ("date", Tag::OffsetTimeOriginal),
+    ("copyright", Tag::Artist),
+    ("copyright", Tag::Copyright),
+];
+
+/// What the camera recorded about a photo, for templates.
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub(crate) struct CameraInfo {
+    pub make: Option<String>,
+    pub model: Option<String>,
+    pub lens: Option<String>,
+    /// e.g. `1/125`, in seconds.
+    pub exposure_time: Option<String>,
+    pub f_number: Option<f64>,
+    pub iso: Option<u32>,
+    /// In millimetres.
+    pub focal_length: Option<f64>,
+    /// When the photo was taken, `YYYY-MM-DD HH:MM:SS` in the camera's time.
+    pub taken: Option<String>,
+}
+
+/// Whether `name` is a group or tag that `exif.keep` accepts.
+pub(crate) fn is_keepable(name: &str) -> bool {
+    KEEPABLE.iter().any(|(group, tag)| keeps(name, group, *tag))
+}
+
+// `name` is a group, or a tag name as the EXIF specification spells it, ignoring case
+fn keeps(name: &str, group: &str, tag: Tag) -> bool {
+    name.eq_ignore_ascii_case(group) || name.eq_ignore_ascii_case(&tag.to_string())
+}
+
+/// The EXIF block for the output images of `input`: the kept tags of the
+/// original plus the configured artist and copyright, `None` when that's nothing.
+pub(crate) fn output_exif(config: &ExifConfig, input: &Path) -> Result<Option<Vec<u8>>, Error> {
+    let original = match config.keep.is_empty() {
+        true => None,
+        false => read(input),
+    };
+    let mut fields: Vec<Field> = original
+        .iter()
+        .flat_map(|exif| exif.fields())
+        .filter(|field| field.ifd_num == In::PRIMARY)
+        .filter(|field| {
+            KEEPABLE.iter().any(|(group, tag)| {
+                *tag == field.tag && config.keep.iter().any(|name| keeps(name, group, *tag))
+            })
+        })
+        .cloned()
+        .collect();
+
+    for (tag, value) in [
+        (Tag::Artist, &config.artist),
+        (Tag::Copyright, &config.copyright),
+    ] {
+        if let Some(value) = value {
+            fields.retain(|field| field.tag != tag);
+            fields.push(Field {
+                tag,
+                ifd_num: In::PRIMARY,
+                value: Value::Ascii(vec![value.as_bytes().to_vec()]),
+            });
+        }
+    }
+    if fields.is_empty() {
+        return Ok(None);
+    }
+
+    let mut writer = Writer::new();
+    for field in fields.iter() {
+        writer.push_field(field);
+    }
+    let mut tiff = std::io::Cursor::new(Vec::new());
+    writer
+        .write(&mut tiff, false)
+        .map_err(|e| Error::Image(input.to_path_buf(), format!("unable to write EXIF: {}", e)))?;
+    Ok(Some(tiff.into_inner()))
+}
+
+/// Insert an EXIF block into an encoded JPEG, as an APP1 segment right after its start marker.
+pub(crate) fn insert_into_jpeg(jpeg: &[u8], tiff: &[u8]) -> Option<Vec<u8>> {
+    // the segment length counts itself and the `Exif\0\0` header, but not the marker
+    let length = u16::try_from(2 + 6 + tiff.len()).ok()?;
+    let rest = jpeg.strip_prefix(&[0xff, 0xd8])?;
+    let mut output = Vec::with_capacity(jpeg.len() + length as usize + 2);
+    output.extend_from_slice(&[0xff, 0xd8, 0xff, 0xe1]);
+    output.extend_from_slice(&length.to_be_bytes());
+    output.extend_from_slice(b"Exif\0\0");
+    output.extend_from_slice(tiff);
+    output.extend_from_slice(rest);
+    Some(output)
+}
+
+/// Camera details of a photo, `None` when it has no EXIF (Instagram strips it from downloads).
+pub(crate) fn camera_info(path: &Path) -> Option<CameraInfo> {
+    let exif = read(path)?;
+    let text = |tag: Tag| {
+        exif.get_field(tag, In::PRIMARY)
+            .map(|field| {
+                field
+                    .display_value()
+                    .to_string()
+                    .trim_matches('"')
+                    .trim()
+                    .to_string()
+            })
+            .filter(|value| !value.is_empty())
+    };
+    let number = |tag: Tag| match &exif.get_field(tag, In::PRIMARY)?.value {
+        Value::Rational(values) => values.first().map(|value| value.to_f64()),
+        value => value.get_uint(0).map(f64::from),
+    };
+
+    let info = CameraInfo {
+        make: text(Tag::Make),
+        model: text(Tag::Model),
+        lens: text(Tag::LensModel),
+        exposure_time: text(Tag::ExposureTime),
+        f_number: number(Tag::FNumber),
+        iso: number(Tag::PhotographicSensitivity).map(|iso| iso as u32),
+        focal_length: number(Tag::FocalLength),
+        taken: text(Tag::DateTimeOriginal),
+    };
+    (info != CameraInfo::default()).then_some(info)
+}
+
+fn read(path: &Path) -> Option<Exif> {
+    let file = File::open(path).ok()?;
+    match Reader::new().read_from_container(&mut BufReader::new(file)) {
+        Ok(exif) => Some(exif),
+        Err(exif::Error::NotFound(_)) => None,
+        Err(e) => {
+            debug!("{}: unreadable EXIF: {}", path.display(), e);
+            None
+        }
+    }
+}
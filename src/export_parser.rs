@@ -0,0 +1,273 @@
+use std::{
+    fs::{self, File},
+    io::Read,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use actix::{Actor, Handler, Message};
+use chrono::DateTime;
+use log::{debug, info, warn};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use zip::ZipArchive;
+
+use crate::{config::Config, error::Error, metadata};
+
+/// Import posts from Instagram's own "Download your information" export.
+///
+/// The export (zip file or extracted directory) is unpacked into the input
+/// directory using Instaloader's file naming, a `<date>_UTC.txt` caption plus
+/// `<date>_UTC.jpg` or `<date>_UTC_<n>.jpg` media, so that `PostFinder` and
+/// `AssetFinder` see the same post/asset model whichever backend produced it.
+/// Responds with the caption filenames that were written.
+#[derive(Message)]
+#[rtype(result = "Result<Vec<String>, Error>")]
+pub(crate) struct ExportMessage(pub PathBuf);
+
+pub(crate) struct ExportParser {
+    pub config: Arc<Config>,
+}
+
+impl Actor for ExportParser {
+    type Context = actix::Context<Self>;
+}
+
+impl Handler<ExportMessage> for ExportParser {
+    type Result = Result<Vec<String>, Error>; // <- Message response type
+
+    fn handle(&mut self, msg: ExportMessage, _ctx: &mut actix::Context<Self>) -> Self::Result {
+        let mut export = Export::open(&msg.0)?;
+        let output_directory = &self.config.input.directory;
+        fs::create_dir_all(output_directory).map_err(Error::io(output_directory))?;
+
+        let mut file_list: Vec<String> = Vec::new();
+        for posts_file in export.post_files() {
+            debug!("reading {}", posts_file);
+            let posts: Vec<ExportPost> = serde_json::from_slice(&export.read(&posts_file)?)
+                .map_err(|e| Error::Export(format!("{}: {}", posts_file, e)))?;
+
+            for post in posts {
+                match write_post(&mut export, output_directory, &post) {
+                    Ok(Some(filename)) => file_list.push(filename),
+                    Ok(None) => warn!("Skipping export post without media: {:?}", post.title),
+                    Err(e) => warn!("Skipping export post {:?}: {}", post.title, e),
+                }
+            }
+        }
+        info!(
+            "Imported {} posts from {}",
+            file_list.len(),
+            msg.0.display()
+        );
+        Ok(file_list)
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct ExportPost {
+    #[serde(default)]
+    media: Vec<ExportMedia>,
+    title: Option<String>,
+    creation_timestamp: Option<i64>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ExportMedia {
+    uri: String,
+    creation_timestamp: Option<i64>,
+    title: Option<String>,
+    /// Only in exports that include the alt text Instagram generated or was given.
+    accessibility_caption: Option<String>,
+}
+
+enum Export {
+    Zip(ZipArchive<File>),
+    Directory(PathBuf),
+}
+
+impl Export {
+    fn open(path: &Path) -> Result<Export, Error> {
+        if path.is_dir() {
+            Ok(Export::Directory(path.to_path_buf()))
+        } else {
+            let archive = ZipArchive::new(File::open(path).map_err(Error::io(path))?)
+                .map_err(|e| Error::Export(format!("{}: {}", path.display(), e)))?;
+            Ok(Export::Zip(archive))
+        }
+    }
+
+    // older exports keep the posts in content/, newer ones in your_instagram_activity/content/
+    fn post_files(&self) -> Vec<String> {
+        let is_posts_file = |name: &str| {
+            let filename = name.rsplit('/').next().unwrap_or(name);
+            name.contains("content/")
+                && filename.starts_with("posts_")
+                && filename.ends_with(".json")
+        };
+        let mut names: Vec<String> = match self {
+            Export::Zip(archive) => archive
+                .file_names()
+                .filter(|name| is_posts_file(name))
+                .map(String::from)
+                .collect(),
+            Export::Directory(root) => {
+                let root_pattern = glob::Pattern::escape(&root.to_string_lossy());
+                glob::glob(&format!("{}/**/content/posts_*.json", root_pattern))
+                    .into_iter()
+                    .flatten()
+                    .filter_map(Result::ok)
+                    .filter_map(|path| {
+                        path.strip_prefix(root)
+                            .ok()
+                            .map(|p| p.to_string_lossy().replace('\\', "/"))
+                    })
+                    .collect()
+            }
+        };
+        names.sort();
+        names
+    }
+
+    // media URIs in the JSON are relative to the export root
+    fn read(&mut self, name: &str) -> Result<Vec<u8>, Error> {
+        let mut contents: Vec<u8> = Vec::new();
+        match self {
+            Export::Zip(archive) => {
+                let mut file = archive
+                    .by_name(name)
+                    .map_err(|e| Error::Export(format!("{}: {}", name, e)))?;
+                file.read_to_end(&mut contents).map_err(Error::io(name))?;
+            }
+            Export::Directory(root) => {
+                let path = root.join(name);
+                contents = fs::read(&path).map_err(Error::io(path))?;
+            }
+        }
+        Ok(contents)
+    }
+}
+
+fn write_post(
+    export: &mut Export,
+    output_directory: &Path,
+    post: &ExportPost,
+) -> Result<Option<String>, Error> {
+    let Some(first_media) = post.media.first() else {
+        return Ok(None);
+    };
+
+    // single media posts only carry the caption and timestamp on the media entry
+    let timestamp = post
+        .creation_timestamp
+        .or(first_media.creation_timestamp)
+        .ok_or_else(|| Error::Export("post without a timestamp".to_string()))?;
+    let caption = post
+        .title
+        .as_deref()
+        .or(first_media.title.as_deref())
+        .map(fix_mojibake)
+        .unwrap_or_default();
+
+    let post_time = DateTime::from_timestamp(timestamp, 0)
+        .ok_or_else(|| Error::Export(format!("invalid timestamp {}", timestamp)))?;
+    let post_stem = format!("{}_UTC", post_time.format("%Y-%m-%d_%H-%M-%S"));
+
+    for (index, media) in post.media.iter().enumerate() {
+        let extension = Path::new(&media.uri)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or("jpg")
+            .to_lowercase();
+        if extension != "jpg" && extension != "mp4" {
+            warn!("{}: unsupported media type {}", post_stem, media.uri);
+        }
+        // Instaloader only numbers the files of carousel posts
+        let media_name = match post.media.len() {
+            1 => format!("{}.{}", post_stem, extension),
+            _ => format!("{}_{}.{}", post_stem, index + 1, extension),
+        };
+        let media_path = output_directory.join(&media_name);
+        if !media_path.exists() {
+            fs::write(&media_path, export.read(&media.uri)?).map_err(Error::io(&media_path))?;
+            debug!("extracted {} to {}", media.uri, media_name);
+        }
+    }
+
+    write_accessibility_captions(output_directory, &post_stem, post)?;
+
+    let caption_name = format!("{}.txt", post_stem);
+    let caption_path = output_directory.join(&caption_name);
+    fs::write(&caption_path, caption).map_err(Error::io(&caption_path))?;
+    Ok(Some(caption_name))
+}
+
+// the export's alt text goes into a sidecar shaped like Instaloader's, which is where
+// the alt text is read from, unless there already is one
+fn write_accessibility_captions(
+    output_directory: &Path,
+    post_stem: &str,
+    post: &ExportPost,
+) -> Result<(), Error> {
+    let captions: Vec<Option<String>> = post
+        .media
+        .iter()
+        .map(|media| media.accessibility_caption.as_deref().map(fix_mojibake))
+        .collect();
+    if captions.iter().all(Option::is_none)
+        || metadata::metadata_path(output_directory, post_stem).is_some()
+    {
+        return Ok(());
+    }
+
+    let node = match captions.as_slice() {
+        [caption] => json!({ "accessibility_caption": caption }),
+        _ => json!({
+            "edge_sidecar_to_children": {
+                "edges": captions
+                    .iter()
+                    .map(|caption| json!({ "node": { "accessibility_caption": caption } }))
+                    .collect::<Vec<Value>>(),
+            }
+        }),
+    };
+    let path = output_directory.join(format!("{}.json", post_stem));
+    let contents = serde_json::to_vec_pretty(&json!({ "node": node }))
+        .map_err(|e| Error::Export(format!("{}: {}", post_stem, e)))?;
+    fs::write(&path, contents).map_err(Error::io(&path))?;
+    debug!("wrote accessibility captions to {}", path.display());
+    Ok(())
+}
+
+/// Undo the export's double encoding: the JSON escapes each UTF-8 byte as its
+/// own code point, so "café" arrives as "cafÃ©". Strings that aren't mojibake
+/// are returned unchanged.
+pub(crate) fn fix_mojibake(input: &str) -> String {
+    let bytes: Option<Vec<u8>> = input
+        .chars()
+        .map(|ch| u8::try_from(u32::from(ch)).ok())
+        .collect();
+    bytes
+        .and_then(|bytes| String::from_utf8(bytes).ok())
+        .unwrap_or_else(|| input.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mojibake_is_decoded() {
+        assert_eq!(fix_mojibake("cafÃ©"), "café");
+        assert_eq!(fix_mojibake("æ\u{9d}±äº¬"), "東京");
+        assert_eq!(fix_mojibake("ð\u{9f}\u{8c}\u{85}"), "🌅");
+    }
+
+    #[test]
+    fn text_that_isnt_mojibake_is_unchanged() {
+        assert_eq!(fix_mojibake("plain text"), "plain text");
+        assert_eq!(fix_mojibake("café"), "café");
+        assert_eq!(fix_mojibake("東京"), "東京");
+        assert_eq!(fix_mojibake(""), "");
+    }
+}
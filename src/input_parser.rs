@@ -1,40 +1,52 @@
+use std::io;
 use std::path::Path;
+use std::sync::Arc;
 
 use actix::{Actor, Handler, Message, System};
-use dotenvy_macro::dotenv;
-use glob::glob;
+use glob::{glob, Pattern};
 use log::error;
 
+use crate::{config::Config, error::Error};
+
 #[derive(Message)]
-#[rtype(result = "Vec<String>")]
+#[rtype(result = "Result<Vec<String>, Error>")]
 pub(crate) struct DirectoryMessage(pub String, pub String);
 
-pub(crate) struct InputParser;
+pub(crate) struct InputParser {
+    pub config: Arc<Config>,
+}
 
 impl Actor for InputParser {
     type Context = actix::Context<Self>;
 }
 
 impl Handler<DirectoryMessage> for InputParser {
-    type Result = Vec<String>; // <- Message response type
+    type Result = Result<Vec<String>, Error>; // <- Message response type
 
     fn handle(&mut self, msg: DirectoryMessage, _ctx: &mut actix::Context<Self>) -> Self::Result {
-        const INPUT_DIRECTORY: &str = dotenv!("INPUT_DIRECTORY");
-
         let mut file_list: Vec<String> = Vec::new();
         // read all text files in directory and add to file_list
-        let pattern = format!("{}/**/{}*_UTC.{}", INPUT_DIRECTORY, msg.0, msg.1);
-
-        for entry in glob(&pattern).expect("Failed to read glob pattern") {
-            match entry {
-                Ok(path) => {
-                    let path_str = &path.to_str().unwrap();
-                    let filename = Path::new(path_str).file_name().unwrap().to_str().unwrap();
-                    file_list.push(String::from(filename.to_owned().to_string()));
-                }
-                Err(e) => error!("{:?}", e),
+        // a `[` in the directory or the filter is part of the name, not a pattern
+        let directory = &self.config.input.directory;
+        let pattern = format!(
+            "{}/**/{}*_UTC.{}",
+            Pattern::escape(&directory.to_string_lossy()),
+            Pattern::escape(&msg.0),
+            msg.1
+        );
+
+        let entries = glob(&pattern).map_err(|e| {
+            Error::Io(
+                directory.clone(),
+                io::Error::new(io::ErrorKind::InvalidInput, e),
+            )
+        })?;
+        for entry in entries {
+            let path = entry.map_err(|e| Error::Io(e.path().to_path_buf(), e.into()))?;
+            if let Some(filename) = path.file_name().and_then(|name| name.to_str()) {
+                file_list.push(filename.to_string());
             }
         }
-        file_list
+        Ok(file_list)
     }
 }
@@ -1,22 +1,374 @@
-use std::borrow::Cow;
+use std::{borrow::Cow, ops::Range};
 
 use lazy_static::lazy_static;
 use regex::{Match, Regex};
 
-pub fn match_and_replace_usernames(input: &str) -> Cow<str> {
+lazy_static! {
+    // a bare URL, the way `caption_to_markdown` links it
+    static ref URL_REGEX: Regex = Regex::new(r"(?:https?://|www\.)[^\s<>]+").unwrap();
+    // letters, marks and digits of any script, and underscores
+    static ref HASHTAG_REGEX: Regex = Regex::new(r"#\w+").unwrap();
+    // dots are allowed between the other characters, a trailing one ends the sentence
+    static ref MENTION_REGEX: Regex = Regex::new(r"@\w(?:[\w.]*\w)?").unwrap();
+}
+
+// Instagram doesn't link a username longer than this
+const MAX_USERNAME_LENGTH: usize = 30;
+
+pub fn match_and_replace_usernames(input: &str) -> Cow<'_, str> {
+    let mentions = find_mentions(input);
+    if mentions.is_empty() {
+        return Cow::Borrowed(input);
+    }
+    let mut output = String::with_capacity(input.len());
+    let mut last = 0;
+    for mention in mentions {
+        let username = &mention.as_str()[1..];
+        output.push_str(&input[last..mention.start()]);
+        output.push_str(&format!(
+            "[@{}](https://www.instagram.com/{})",
+            username, username
+        ));
+        last = mention.end();
+    }
+    output.push_str(&input[last..]);
+    Cow::Owned(output)
+}
+
+pub fn find_hashtags(input: &str) -> Vec<Match<'_>> {
+    // a hashtag of only digits isn't one
+    find_tokens(&HASHTAG_REGEX, input, &['&'])
+        .filter(|hashtag| !hashtag.as_str()[1..].chars().all(|ch| ch.is_numeric()))
+        .collect()
+}
+
+/// `@username` mentions, not the middle of an email address or a URL.
+pub fn find_mentions(input: &str) -> Vec<Match<'_>> {
+    find_tokens(&MENTION_REGEX, input, &['.', '@'])
+        .filter(|mention| mention.as_str()[1..].chars().count() <= MAX_USERNAME_LENGTH)
+        .collect()
+}
+
+// matches that start a word, i.e. don't follow a letter, digit, underscore or one of
+// `after`, and that aren't part of a URL
+fn find_tokens<'a>(
+    regex: &'a Regex,
+    input: &'a str,
+    after: &'a [char],
+) -> impl Iterator<Item = Match<'a>> + 'a {
+    let urls: Vec<Range<usize>> = URL_REGEX.find_iter(input).map(|url| url.range()).collect();
+    regex.find_iter(input).filter(move |token| {
+        let starts_word = input[..token.start()]
+            .chars()
+            .last()
+            .is_none_or(|ch| !is_word_char(ch) && !after.contains(&ch));
+        let in_url = urls
+            .iter()
+            .any(|url| url.start <= token.start() && token.start() < url.end);
+        starts_word && !in_url
+    })
+}
+
+fn is_word_char(ch: char) -> bool {
+    ch.is_alphanumeric() || ch == '_'
+}
+
+/// The caption up to the end of its first sentence or line, without hashtags.
+pub fn first_sentence(caption: &str) -> String {
+    let mut without_hashtags = caption.to_string();
+    for hashtag in find_hashtags(caption).iter().rev() {
+        without_hashtags.replace_range(hashtag.range(), "");
+    }
+    lazy_static! {
+        static ref LIST_NUMBER_REGEX: Regex = Regex::new(r"^\d+[.)]\s+").unwrap();
+    }
+    // the number of a list isn't a sentence of its own
+    let text = without_hashtags.trim_start();
+    let start = LIST_NUMBER_REGEX
+        .find(text)
+        .map_or(0, |number| number.end());
+    let text = &text[start..];
+    let end = text
+        .char_indices()
+        .find(|(position, ch)| {
+            *ch == '\n'
+                || (matches!(ch, '.' | '!' | '?')
+                    && text[position + 1..].starts_with(char::is_whitespace))
+        })
+        .map(|(position, ch)| match ch {
+            '\n' => position,
+            _ => position + 1,
+        })
+        .unwrap_or(text.len());
+    text[..end]
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
+
+/// Turn a caption, after `match_and_replace_usernames`, into Markdown: characters
+/// Markdown would act on are escaped, line breaks are kept, blank lines start a new
+/// paragraph and bare URLs become links. The username links are left as they are,
+/// hashtags are linked to the URL `tag_url` returns for them.
+pub fn caption_to_markdown(input: &str, tag_url: impl Fn(&str) -> Option<String>) -> String {
+    let mut paragraphs: Vec<Vec<String>> = vec![Vec::new()];
+    for line in input.lines().map(str::trim) {
+        match is_spacer(line) {
+            true if paragraphs.last().is_some_and(|lines| !lines.is_empty()) => {
+                paragraphs.push(Vec::new())
+            }
+            true => {}
+            false => paragraphs
+                .last_mut()
+                .unwrap()
+                .push(markdown_line(line, &tag_url)),
+        }
+    }
+    paragraphs
+        .iter()
+        .filter(|lines| !lines.is_empty())
+        // a single line break is a hard break, two trailing spaces
+        .map(|lines| lines.join("  \n"))
+        .collect::<Vec<String>>()
+        .join("\n\n")
+}
+
+// Instagram drops empty lines, so captions space out paragraphs with a lone dot
+// or an invisible character instead
+fn is_spacer(line: &str) -> bool {
+    matches!(line, "." | "-" | "_" | "•")
+        || line.chars().all(|ch| {
+            ch.is_whitespace() || matches!(ch, '\u{2800}' | '\u{200b}'..='\u{200d}' | '\u{feff}')
+        })
+}
+
+fn markdown_line(line: &str, tag_url: &impl Fn(&str) -> Option<String>) -> String {
+    lazy_static! {
+        static ref USERNAME_LINK_REGEX: Regex =
+            Regex::new(r"\[@[\w.]+\]\(https://www\.instagram\.com/[\w.]+\)").unwrap();
+    }
+    // the username links were made before, their URLs aren't linked again
+    let mut links: Vec<Match> = USERNAME_LINK_REGEX.find_iter(line).collect();
+    links.extend(URL_REGEX.find_iter(line));
+    links.extend(find_hashtags(line));
+    links.sort_by_key(|link| (link.start(), std::cmp::Reverse(link.end())));
+
+    let mut output = String::with_capacity(line.len());
+    let mut last = 0;
+    for link in links {
+        if link.start() < last {
+            continue;
+        }
+        let (link_text, rest) = split_trailing_punctuation(link.as_str());
+        output.push_str(&escape_markdown(&line[last..link.start()], last == 0));
+        if link_text.starts_with('[') {
+            output.push_str(link_text);
+        } else if let Some(tag) = link_text.strip_prefix('#') {
+            match tag_url(tag) {
+                Some(url) => {
+                    output.push_str(&format!("[#{}]({})", escape_markdown(tag, false), url))
+                }
+                None => {
+                    output.push_str(&escape_markdown(link_text, last == 0 && link.start() == 0))
+                }
+            }
+        } else if link_text.starts_with("www.") {
+            output.push_str(&format!(
+                "[{}](https://{})",
+                escape_markdown(link_text, false),
+                link_text
+            ));
+        } else {
+            output.push_str(&format!("<{}>", link_text));
+        }
+        output.push_str(&escape_markdown(rest, false));
+        last = link.end();
+    }
+    output.push_str(&escape_markdown(&line[last..], last == 0));
+    output
+}
+
+// a sentence's full stop or a closing bracket that wasn't opened in the URL isn't part of it
+fn split_trailing_punctuation(link: &str) -> (&str, &str) {
+    if link.starts_with(['[', '#']) {
+        return (link, "");
+    }
+    let mut end = link.len();
+    while let Some(ch) = link[..end].chars().last() {
+        let unbalanced =
+            ch == ')' && link[..end].matches('(').count() < link[..end].matches(')').count();
+        if !matches!(ch, '.' | ',' | ':' | ';' | '!' | '?' | '"' | '\'') && !unbalanced {
+            break;
+        }
+        end -= ch.len_utf8();
+    }
+    link.split_at(end)
+}
+
+// backslash escapes for what's inline Markdown anywhere, and for what starts a heading,
+// quote, list or rule at the start of a line
+fn escape_markdown(text: &str, line_start: bool) -> String {
+    let mut output = String::with_capacity(text.len());
+    for ch in text.chars() {
+        if matches!(
+            ch,
+            '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '~' | '|'
+        ) {
+            output.push('\\');
+        }
+        output.push(ch);
+    }
+    if !line_start {
+        return output;
+    }
     lazy_static! {
-        static ref USERNAME_REGEX: Regex =
-            Regex::new(r"\@(?P<username>[a-zA-Z][0-9a-zA-Z_]*)").unwrap();
+        static ref LINE_START_REGEX: Regex = Regex::new(r"^(?:[#+=-]|(\d+)([.)]))").unwrap();
     }
-    let replacement_text: String = format!(
-        "[@{}](https://www.instagram.com/{})",
-        "$username", "$username"
-    );
-    USERNAME_REGEX.replace_all(input, replacement_text)
+    LINE_START_REGEX
+        .replace(&output, |captures: &regex::Captures| {
+            match captures.get(1) {
+                Some(number) => format!("{}\\{}", number.as_str(), &captures[2]),
+                None => format!("\\{}", &captures[0]),
+            }
+        })
+        .to_string()
 }
 
-pub fn find_hashtags(input: &str) -> Vec<Match> {
-    let regex = Regex::new(r"#[a-zA-Z][0-9a-zA-Z_]*").unwrap();
-    let matches: Vec<_> = regex.find_iter(input).collect();
-    matches
+/// The caption without the hashtags that end it, when they are on lines of their own,
+/// spacer lines in between included.
+pub fn strip_trailing_hashtags(input: &str) -> &str {
+    let mut end = input.trim_end().len();
+    while let Some(line) = input[..end].lines().last() {
+        let line_start = end - line.len();
+        let only_hashtags = line.split_whitespace().all(|word| {
+            find_hashtags(word)
+                .first()
+                .is_some_and(|hashtag| hashtag.range() == (0..word.len()))
+        });
+        if !only_hashtags && !is_spacer(line.trim()) {
+            break;
+        }
+        end = input[..line_start].trim_end().len();
+    }
+    &input[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_sentence_of_caption() {
+        assert_eq!(
+            first_sentence("#london Sunset over  the Thames. More soon"),
+            "Sunset over the Thames."
+        );
+        assert_eq!(
+            first_sentence("Version 2.0 is out\nsecond line"),
+            "Version 2.0 is out"
+        );
+        assert_eq!(
+            first_sentence("Me @jörg.photo, see me@example.com! Bye"),
+            "Me @jörg.photo, see me@example.com!"
+        );
+        assert_eq!(first_sentence("#only #hashtags"), "");
+    }
+
+    #[test]
+    fn first_sentence_skips_list_numbers() {
+        assert_eq!(first_sentence("1. first"), "first");
+        assert_eq!(first_sentence("2) second. third"), "second.");
+    }
+
+    fn strs(matches: Vec<Match<'_>>) -> Vec<&str> {
+        matches.iter().map(|m| m.as_str()).collect()
+    }
+
+    #[test]
+    fn hashtags_of_any_script() {
+        assert_eq!(
+            strs(find_hashtags("#café in #東京, #new_york")),
+            ["#café", "#東京", "#new_york"]
+        );
+    }
+
+    #[test]
+    fn hashtags_start_a_word() {
+        assert_eq!(strs(find_hashtags("#2023 issue#4 it&#39;s #1st")), ["#1st"]);
+        assert!(find_hashtags("https://example.com/page#section").is_empty());
+    }
+
+    #[test]
+    fn mentions_with_dots() {
+        assert_eq!(
+            strs(find_mentions("Shot by @jörg.photo. Thanks @a_b!")),
+            ["@jörg.photo", "@a_b"]
+        );
+    }
+
+    #[test]
+    fn mentions_not_in_emails_or_urls() {
+        assert!(find_mentions("mail me@example.com").is_empty());
+        assert!(find_mentions("see https://example.com/@someone").is_empty());
+        assert!(find_mentions(&format!("@{}", "a".repeat(31))).is_empty());
+    }
+
+    #[test]
+    fn usernames_become_links() {
+        assert_eq!(
+            match_and_replace_usernames("by @jörg.photo."),
+            "by [@jörg.photo](https://www.instagram.com/jörg.photo)."
+        );
+    }
+
+    #[test]
+    fn markdown_is_escaped() {
+        let markdown = caption_to_markdown("*so* [good] <3\n1. first\n# not a heading", |_| None);
+        assert_eq!(
+            markdown,
+            "\\*so\\* \\[good\\] \\<3  \n1\\. first  \n\\# not a heading"
+        );
+    }
+
+    #[test]
+    fn markdown_paragraphs() {
+        assert_eq!(
+            caption_to_markdown("one\ntwo\n.\n\u{2800}\nthree", |_| None),
+            "one  \ntwo\n\nthree"
+        );
+    }
+
+    #[test]
+    fn markdown_links() {
+        let caption = match_and_replace_usernames("by @jörg.photo at https://example.com/a_(b).");
+        assert_eq!(
+            caption_to_markdown(&caption, |_| None),
+            "by [@jörg.photo](https://www.instagram.com/jörg.photo) at <https://example.com/a_(b)>."
+        );
+        assert_eq!(
+            caption_to_markdown("www.example.com #my_tag", |_| None),
+            "[www.example.com](https://www.example.com) #my\\_tag"
+        );
+    }
+
+    #[test]
+    fn hashtags_are_linked() {
+        assert_eq!(
+            caption_to_markdown("at the #café. #my_tag", |tag| Some(format!(
+                "/tags/{}/",
+                tag
+            ))),
+            "at the [#café](/tags/café/). [#my\\_tag](/tags/my_tag/)"
+        );
+    }
+
+    #[test]
+    fn trailing_hashtags_are_stripped() {
+        assert_eq!(
+            strip_trailing_hashtags("Sunset #london\n.\n#london #uk\n#sky\n"),
+            "Sunset #london"
+        );
+        assert_eq!(strip_trailing_hashtags("Sunset #london"), "Sunset #london");
+        assert_eq!(strip_trailing_hashtags("#london #uk"), "");
+    }
 }
@@ -10,16 +10,16 @@ use std::{
 use std::collections::HashMap;
 use std::collections::VecDeque;
 use std::hash::Hash;
-use std::io::Error;
 use std::iter::{Flatten, Map};
 use std::path::PathBuf;
+use std::sync::Arc;
 
 use actix::{Actor, Addr, fut::result, Handler, MailboxError, Message, SyncArbiter, System};
 use actix::dev::Request;
 use actix_rt::Arbiter;
 use chrono::{Datelike, DateTime, format::parse, NaiveDateTime, Utc, Weekday};
+use clap::Parser;
 use dotenvy::dotenv;
-use dotenvy_macro::dotenv;
 
 use env_logger::{Builder, Target};
 use future::try_join_all;
@@ -41,21 +41,48 @@ use regex::{Captures, Regex};
 use serde::{Deserialize, Serialize};
 use tera::{Context, Tera};
 
+use export_parser::{ExportMessage, ExportParser};
 use input_parser::{DirectoryMessage, InputParser};
 
 use crate::{
     asset_finder::{AssetFinder, AssetMessage},
-    media_processor::{MediaMessage, MediaProcessor},
+    cli::{Cli, Command},
+    config::Config,
+    error::Error,
+    exif_data::CameraInfo,
+    manifest::{Manifest, ManifestEntry},
+    media_processor::{MediaMessage, MediaOutput, MediaProcessor},
+    metadata::PostMetadata,
+    placeholder::Placeholder,
     post_finder::{PostFinder, PostFinderMessage},
+    report::BuildReport,
 };
 use crate::post_actor::{PostActor, PostMessage};
 
+mod alt_text;
 mod asset_finder;
+mod clean;
+mod cli;
+mod config;
+mod duplicates;
+mod error;
+mod exif_data;
+mod export_parser;
 mod input_parser;
 mod instagram;
+mod manifest;
 mod media_processor;
+mod metadata;
+mod output_target;
+mod placeholder;
 mod post_actor;
 mod post_finder;
+mod report;
+mod tags;
+mod thumbnail;
+mod title;
+mod video;
+mod watermark;
 
 #[derive(Serialize, Debug)]
 struct Post {
@@ -67,8 +94,75 @@ struct Post {
     tags: Vec<String>,
     heading: String,
     text: String,
-    images: Vec<String>,
+    images: Vec<PostImage>,
     filename: String,
+    slug: String,
+    front_matter: String,
+    metadata: Option<PostMetadata>,
+    /// Camera details of the first image that has them.
+    camera: Option<CameraInfo>,
+}
+
+/// An image or video of a post, with the renditions a template can put in `srcset`.
+#[derive(Serialize, Debug, Clone)]
+struct PostImage {
+    /// Position in the carousel, starting at 1.
+    index: usize,
+    media_type: MediaType,
+    url: String,
+    /// URL of the square thumbnail, none for a video without a poster.
+    thumbnail: Option<String>,
+    width: u32,
+    height: u32,
+    /// Width divided by height, none when the size is unknown.
+    aspect_ratio: Option<f64>,
+    orientation: Option<Orientation>,
+    /// Description of the image for `alt`, empty when there is none.
+    alt: String,
+    /// `url 480w, url 960w, ...` narrowest first, ready for `<img srcset>`.
+    srcset: String,
+    sources: Vec<ImageSource>,
+    /// The same renditions in the extra formats, for `<picture><source>`s.
+    alternates: Vec<ImageAlternate>,
+    is_video: bool,
+    /// URL of a video's poster image, for `<video poster>`.
+    poster: Option<String>,
+    /// Length of a video in seconds.
+    duration: Option<f64>,
+    /// EXIF camera details of a photo, when the original still has them.
+    camera: Option<CameraInfo>,
+    /// BlurHash, inline preview and dominant colour, for lazy loading.
+    placeholder: Option<Placeholder>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum MediaType {
+    Image,
+    Video,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum Orientation {
+    Landscape,
+    Portrait,
+    Square,
+}
+
+#[derive(Serialize, Debug, Clone)]
+struct ImageAlternate {
+    #[serde(rename = "type")]
+    mime_type: String,
+    srcset: String,
+    sources: Vec<ImageSource>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+struct ImageSource {
+    url: String,
+    width: u32,
+    height: u32,
 }
 
 impl Post {
@@ -84,10 +178,19 @@ impl Post {
             text: "".to_string(),
             images: Vec::new(),
             filename: "".to_string(),
+            slug: "".to_string(),
+            front_matter: "".to_string(),
+            metadata: None,
+            camera: None,
         }
     }
 }
 
+// date/time tokens and output path of a post, see PostFinder
+type PostDates = HashMap<String, String>;
+type PostAssets = (PostDates, Vec<String>);
+type StageResults<T> = Result<Vec<Result<T, Error>>, MailboxError>;
+
 struct InMemoryStore {
     lines: VecDeque<String>,
 }
@@ -110,71 +213,165 @@ impl InMemoryStore {
 
 #[actix::main]
 async fn main() {
-    dotenv().expect(".env file not found");
-    env_logger::init();
-    run_processor().await;
+    // a .env file is optional, its values are picked up as environment overrides
+    dotenv().ok();
+
+    let cli = Cli::parse();
+    Builder::new()
+        .filter_level(cli.log_level())
+        .parse_default_env()
+        .init();
+
+    let config = match Config::load(&cli) {
+        Ok(config) => Arc::new(config),
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(1);
+        }
+    };
+
+    match cli.command() {
+        Command::Clean { dry_run } => match clean::clean_output(&config, dry_run) {
+            Ok(count) => info!("Removed {} files", count),
+            Err(e) => {
+                error!(
+                    "Unable to clean {}: {}",
+                    config.output.directory.display(),
+                    e
+                );
+                exit(1);
+            }
+        },
+        command => run_processor(config, command, cli.force).await,
+    }
 }
 
-async fn run_processor() {
+async fn run_processor(config: Arc<Config>, command: Command, force: bool) {
     let arbiters = create_arbiters();
+    let mut manifest = Manifest::load(&config);
 
     let mut post_store = InMemoryStore::new();
     let mut asset_store = InMemoryStore::new();
 
-    let res = read_files().await; // <- send message and get future for result
+    let res = read_files(&config).await; // <- send message and get future for result
 
     // res will contain a list of files ending in .txt
     match res {
         Ok(result) => {
+            // slugs have to be unique among all the posts, not just those of this run
+            let all_posts = result.clone();
+            let result = filter_posts(&config, result);
             for (index, filename) in result.clone().iter().enumerate() {
                 post_store.add_line(filename.to_string());
             }
 
+            if command == Command::Scan {
+                for filename in post_store.read_all_lines() {
+                    println!("{}", filename);
+                }
+                info!("Total Files: {}", &result.len());
+                return;
+            }
+
+            let mut report = BuildReport::default();
+
             // Step 1
-            let post_res: Result<Vec<Result<HashMap<String, String>, Error>>, MailboxError> =
-                find_posts(&arbiters, post_store.read_all_lines()).await;
+            let post_names = post_store.read_all_lines();
+            let post_res = find_posts(&arbiters, &config, post_names.clone()).await;
+            let posts = report.collect("scan", post_names, post_res);
 
             // Step 2
-            let asset_list: Result<
-                Vec<Result<(HashMap<String, String>, Vec<String>), Error>>,
-                MailboxError,
-            > = find_media(&arbiters, post_res).await;
+            let post_names: Vec<String> = posts.iter().map(|(name, _)| name.clone()).collect();
+            let asset_list = find_media(&arbiters, &config, posts).await;
+            let asset_results = report.collect("find media", post_names, asset_list);
+
+            let post_media: Vec<(String, Vec<String>)> = asset_results
+                .into_iter()
+                .map(|(post_name, (_, assets))| {
+                    let asset_filenames = assets
+                        .iter()
+                        .map(|asset| {
+                            Path::new(&asset)
+                                .file_name()
+                                .map(|name| name.to_string_lossy().to_string())
+                                .unwrap_or_default()
+                        })
+                        .collect();
+                    (post_name, asset_filenames)
+                })
+                .collect();
+            let duplicates = duplicates::find(&config, &mut manifest, &post_media);
 
             // Step 3
-
-            // Unwrap the outer Result
-            if let Ok(asset_results) = asset_list {
-                // Collect all the inner Results into a single Vec
-                let mut all_assets: Vec<String> = Vec::new();
-                for asset_result in asset_results {
-                    // Unwrap each inner Result
-                    if let Ok((_, assets)) = asset_result {
-                        // Add the assets to the all_assets Vec
-                        all_assets.extend(assets);
-                    }
+            // only the posts whose media was found carry on to the later stages
+            let mut post_store = InMemoryStore::new();
+            for (post_name, assets) in post_media {
+                if duplicates.skipped.contains(&post_name) {
+                    continue;
                 }
-                for asset in all_assets.iter() {
-                    let asset_filename = Path::new(&asset).file_name().unwrap().to_str().unwrap();
-                    asset_store.add_line(asset_filename.to_string());
+                post_store.add_line(post_name);
+                for asset in assets {
+                    asset_store.add_line(asset);
                 }
-                // Now asset_store contains all the assets from all the Results
             }
             // debug!("all_assets: {:?}", asset_store.read_all_lines());
-            process_media(&arbiters, asset_store.read_all_lines()).await;
+            let mut failed_media: HashSet<String> = HashSet::new();
+            if matches!(command, Command::Media | Command::Build) {
+                // reused assets link to the output of the asset they duplicate
+                let media: Vec<String> = asset_store
+                    .read_all_lines()
+                    .into_iter()
+                    .filter(|asset| !duplicates.reused.contains_key(asset))
+                    .collect();
+                failed_media =
+                    process_media(&arbiters, &config, media, &mut manifest, &mut report, force)
+                        .await;
+            }
 
             // Step 4
-            render_posts(
-                &arbiters,
-                post_store.read_all_lines(),
-                asset_store.read_all_lines(),
-            )
-            .await;
+            if matches!(command, Command::Render | Command::Build) {
+                // posts with a broken image aren't rendered, the image is already in the report
+                let posts: Vec<String> = post_store
+                    .read_all_lines()
+                    .into_iter()
+                    .filter(|post| {
+                        let post_stem = post_actor::post_stem_from_asset(post);
+                        !failed_media.iter().any(|asset| {
+                            asset.starts_with(post_stem)
+                                || duplicates.reused.iter().any(|(reused, original)| {
+                                    reused.starts_with(post_stem) && original == asset
+                                })
+                        })
+                    })
+                    .collect();
+                let slugs = title::unique_slugs(&config, &all_posts);
+                render_posts(
+                    &arbiters,
+                    &config,
+                    posts,
+                    &slugs,
+                    asset_store.read_all_lines(),
+                    &duplicates.reused,
+                    &mut manifest,
+                    &mut report,
+                    force,
+                )
+                .await;
+            }
+
+            if let Err(e) = manifest.save(&config) {
+                error!("Unable to save the build manifest: {}", e);
+            }
 
             info!("Total Files: {}", &result.len());
+            if !report.is_empty() {
+                eprint!("{}", report);
+                exit(1);
+            }
         }
         Err(e) => {
-            error!("Communication to the actor has failed");
-            exit(0);
+            error!("Unable to list the posts: {}", e);
+            exit(1);
         }
     }
 }
@@ -189,44 +386,168 @@ fn create_arbiters() -> Vec<Arbiter> {
 
 // read all the text files in the input directory
 // turn all the file names into a vector of strings
-async fn read_files() -> Result<Vec<String>, MailboxError> {
-    let input_addr: Addr<InputParser> = InputParser.start();
-    const TEST_INPUT: &str = dotenv!("TEST_INPUT");
+async fn read_files(config: &Arc<Config>) -> Result<Vec<String>, Error> {
+    // an official Instagram export is unpacked into the input directory first
+    if let Some(export) = &config.input.export {
+        let export_addr: Addr<ExportParser> = ExportParser {
+            config: config.clone(),
+        }
+        .start();
+        if let Err(e) = export_addr.send(ExportMessage(export.clone())).await? {
+            error!("Unable to import {}: {}", export.display(), e);
+            exit(1);
+        }
+    }
 
-    debug!("TEST_INPUT: {}", TEST_INPUT);
+    let input_addr: Addr<InputParser> = InputParser {
+        config: config.clone(),
+    }
+    .start();
 
-    let res: Result<Vec<String>, MailboxError> = input_addr
-        .send(DirectoryMessage(TEST_INPUT.to_owned(), "txt".to_owned()))
-        .await; // <- send message and get future for result
-    res
+    // every post, the filters are applied by filter_posts
+    input_addr
+        .send(DirectoryMessage(String::new(), "txt".to_owned()))
+        .await? // <- send message and get future for result
 }
 
+// drop posts that don't start with the --filter prefix or are outside the --since/--until range
+fn filter_posts(config: &Config, posts: Vec<String>) -> Vec<String> {
+    debug!("input filter: {}", config.input.filter);
+    posts
+        .into_iter()
+        .filter(|post| post.starts_with(&config.input.filter))
+        .filter(|post| {
+            let date = post
+                .get(..10)
+                .and_then(|prefix| chrono::NaiveDate::parse_from_str(prefix, "%Y-%m-%d").ok());
+            match date {
+                Some(date) => {
+                    config.input.since.is_none_or(|since| date >= since)
+                        && config.input.until.is_none_or(|until| date <= until)
+                }
+                None => config.input.since.is_none() && config.input.until.is_none(),
+            }
+        })
+        .collect()
+}
+
+#[allow(clippy::too_many_arguments)]
 async fn render_posts(
-    arbiters: &Vec<Arbiter>,
+    arbiters: &[Arbiter],
+    config: &Arc<Config>,
     posts: Vec<String>,
+    slugs: &HashMap<String, String>,
     assets: Vec<String>,
-) -> Result<Vec<Result<(), Error>>, MailboxError> {
+    reused: &HashMap<String, String>,
+    manifest: &mut Manifest,
+    report: &mut BuildReport,
+    force: bool,
+) {
     // we have the list of posts and assets, let's render the posts
     let mut post_futs: Vec<Request<PostActor, PostMessage>> = Vec::new();
+    let mut rendering: Vec<(String, String)> = Vec::new();
+    let templates_hash = manifest::templates_hash(config);
 
     for (index, file_name) in posts.iter().enumerate() {
-        debug!("Render Post #{} of {}", index, posts.len());
         // choose an arbiter to use
         let arb: &Arbiter = &arbiters[index % arbiters.len()];
         debug!("arb: {:?}", arb);
 
-        let post_stem = Path::new(&file_name).file_stem().unwrap().to_str().unwrap();
+        let post_stem = post_actor::post_stem_from_asset(file_name);
         let post_assets = find_lines_starting_with(assets.clone(), post_stem);
 
         debug!("Found assets for post {}: {:?}", post_stem, post_assets);
 
+        // the caption, metadata, media (whose size sets the srcset) and templates decide what the markdown looks like
+        let mut post_inputs = vec![config.input.directory.join(file_name)];
+        post_inputs.extend(metadata::metadata_path(&config.input.directory, post_stem));
+        post_inputs.extend(config.alt.overrides.clone());
+        post_inputs.extend(config.tags.mapping.clone());
+        post_inputs.extend(
+            post_assets
+                .iter()
+                .map(|asset| config.input.directory.join(asset)),
+        );
+        // sizes and placeholders come from the media stage, a post rendered before it ran has none
+        let media: HashMap<String, ManifestEntry> = post_assets
+            .iter()
+            .filter_map(|asset| {
+                let source = reused.get(asset).unwrap_or(asset);
+                Some((asset.clone(), manifest.media.get(source)?.clone()))
+            })
+            .collect();
+        let post_reused: HashMap<String, String> = post_assets
+            .iter()
+            .filter_map(|asset| Some((asset.clone(), reused.get(asset)?.clone())))
+            .collect();
+        let slug = slugs.get(file_name).cloned();
+        let input_hash = manifest::hash_inputs(
+            &post_inputs,
+            &[
+                &post_assets.join("\n"),
+                &templates_hash,
+                &config.alt.template,
+                &format!("{:?}", config.captions),
+                &format!("{:?}{:?}", config.titles, config.slugs),
+                slug.as_deref().unwrap_or_default(),
+                &post_assets
+                    .iter()
+                    .map(|asset| {
+                        let processed = media
+                            .get(asset)
+                            .map(|entry| (&entry.placeholder, &entry.sizes, &entry.video));
+                        format!("{:?}{:?}", processed, reused.get(asset))
+                    })
+                    .collect::<String>(),
+            ],
+        );
+        if !force
+            && manifest
+                .posts
+                .get(file_name)
+                .is_some_and(|entry| entry.is_fresh(&input_hash, &config.output.directory))
+        {
+            debug!("Post {} is unchanged, skipping", post_stem);
+            continue;
+        }
+        rendering.push((file_name.to_string(), input_hash));
+        debug!("Render Post #{} of {}", index, posts.len());
+
+        let actor_config = config.clone();
+
         let post_renderer: Addr<PostActor> =
-            PostActor::start_in_arbiter(&arb.handle(), |_ctx| PostActor);
-        post_futs.push(post_renderer.send(PostMessage(file_name.to_string(), post_assets)));
+            PostActor::start_in_arbiter(&arb.handle(), move |_ctx| PostActor {
+                config: actor_config,
+            });
+        post_futs.push(post_renderer.send(PostMessage(
+            file_name.to_string(),
+            post_assets,
+            media,
+            post_reused,
+            slug,
+        )));
         debug!("sent path to PostActor");
     }
 
-    try_join_all(post_futs).await
+    let post_res = try_join_all(post_futs).await;
+    let (file_names, input_hashes): (Vec<String>, Vec<String>) = rendering.into_iter().unzip();
+    let hashes: HashMap<String, String> = file_names.iter().cloned().zip(input_hashes).collect();
+    let rendered = report.collect("render", file_names, post_res);
+    info!("Rendered {} of {} posts", rendered.len(), posts.len());
+
+    for (file_name, output_path) in rendered {
+        let entry = ManifestEntry::new(
+            hashes[&file_name].clone(),
+            vec![PathBuf::from(output_path)],
+            &config.output.directory,
+        );
+        manifest::record(
+            &mut manifest.posts,
+            file_name,
+            entry,
+            &config.output.directory,
+        );
+    }
 }
 
 fn find_lines_starting_with(lines: Vec<String>, start: &str) -> Vec<String> {
@@ -237,35 +558,77 @@ fn find_lines_starting_with(lines: Vec<String>, start: &str) -> Vec<String> {
 }
 
 async fn process_media(
-    arbiters: &Vec<Arbiter>,
+    arbiters: &[Arbiter],
+    config: &Arc<Config>,
     asset_list: Vec<String>,
-) -> Result<Vec<Result<HashMap<String, String>, Error>>, MailboxError> {
-    // we have a complete list of media files, let's process them
+    manifest: &mut Manifest,
+    report: &mut BuildReport,
+    force: bool,
+) -> HashSet<String> {
+    // we have a complete list of media files, let's process them,
+    // returning the filenames of those that failed
 
     let mut file_futs: Vec<Request<MediaProcessor, MediaMessage>> = Vec::new();
+    let mut processing: Vec<(String, String)> = Vec::new();
 
     for (index, filepath) in asset_list.iter().enumerate() {
-        // check file extensions and only send images to be processed
-        let mut path: PathBuf = PathBuf::from(&filepath);
-        let filename = &path.file_name().unwrap().to_str().unwrap();
-        let file_extension = Path::new(&filename).extension().unwrap().to_str().unwrap();
-        if file_extension == "mp4" {
-            debug!("process_media: skipping video");
+        let filename = filepath.as_str();
+
+        // the watermark rules can depend on the caption, so the watermark this image gets is part of its inputs
+        let mark = watermark::resolve(config, filename).ok().flatten();
+        let mut media_inputs = vec![config.input.directory.join(filename)];
+        media_inputs.extend(mark.as_ref().map(|mark| mark.image.clone()));
+        media_inputs.extend(
+            video::is_video(filename)
+                .then(|| video::sidecar_poster(&config.input.directory, filename))
+                .flatten(),
+        );
+        let input_hash = manifest::hash_inputs(&media_inputs, &[&format!("{:?}", mark)]);
+        if !force
+            && manifest
+                .media
+                .get(filename)
+                .is_some_and(|entry| entry.is_fresh(&input_hash, &config.output.directory))
+        {
+            debug!("process_media: {} is unchanged, skipping", filename);
             continue;
         }
+        processing.push((filename.to_string(), input_hash));
 
         // choose an arbiter to use
         let arb: &Arbiter = &arbiters[index % arbiters.len()];
+        let actor_config = config.clone();
         let asset_addr: Addr<MediaProcessor> =
-            MediaProcessor::start_in_arbiter(&arb.handle(), |_ctx| MediaProcessor);
+            MediaProcessor::start_in_arbiter(&arb.handle(), move |_ctx| MediaProcessor {
+                config: actor_config,
+            });
         debug!("process_media: {}", filepath);
         file_futs.push(asset_addr.send(MediaMessage(filepath.to_string())));
     }
-    let file_res: Result<Vec<Result<HashMap<String, String>, Error>>, MailboxError> =
-        try_join_all(file_futs).await;
+    let file_res: StageResults<MediaOutput> = try_join_all(file_futs).await;
 
     debug!("process_media file_res: {:?}", file_res);
-    file_res
+    let (filenames, input_hashes): (Vec<String>, Vec<String>) = processing.into_iter().unzip();
+    let mut failed: HashSet<String> = filenames.iter().cloned().collect();
+    let hashes: HashMap<String, String> = filenames.iter().cloned().zip(input_hashes).collect();
+
+    for (filename, media) in report.collect("media", filenames, file_res) {
+        let mut outputs: Vec<PathBuf> = media.files.values().map(PathBuf::from).collect();
+        outputs.sort();
+        let mut entry =
+            ManifestEntry::new(hashes[&filename].clone(), outputs, &config.output.directory);
+        entry.placeholder = media.placeholder;
+        entry.sizes = media.sizes;
+        entry.video = media.video;
+        failed.remove(&filename);
+        manifest::record(
+            &mut manifest.media,
+            filename,
+            entry,
+            &config.output.directory,
+        );
+    }
+    failed
 }
 
 // async fn build_post(arbiters: &Vec<Arbiter>, raw_post_file: String, media_files: Vec<String>) {
@@ -279,28 +642,28 @@ async fn process_media(
 // }
 
 async fn find_media(
-    arbiters: &Vec<Arbiter>,
-    post_res: Result<Vec<Result<HashMap<String, String>, Error>>, MailboxError>,
-) -> Result<Vec<Result<(HashMap<String, String>, Vec<String>), Error>>, MailboxError> {
+    arbiters: &[Arbiter],
+    config: &Arc<Config>,
+    posts: Vec<(String, PostDates)>,
+) -> StageResults<PostAssets> {
     // we have the posts, let's get the list of media files
     let mut asset_futs: Vec<Request<AssetFinder, AssetMessage>> = Vec::new();
-    for (index, data) in post_res.unwrap().iter().enumerate() {
+    for (index, (_, datetime_dictionary)) in posts.into_iter().enumerate() {
         // choose an arbiter to use
         let arb: &Arbiter = &arbiters[index % arbiters.len()];
         debug!("arb: {:?}", arb);
 
-        // this dictionary contains the tokenized date/time information
-        let datetime_dictionary: HashMap<String, String> = data.as_ref().unwrap().clone();
+        // the dictionary contains the tokenized date/time information
+        let actor_config = config.clone();
 
         let asset_addr: Addr<AssetFinder> =
-            AssetFinder::start_in_arbiter(&arb.handle(), |_ctx| AssetFinder);
+            AssetFinder::start_in_arbiter(&arb.handle(), move |_ctx| AssetFinder {
+                config: actor_config,
+            });
         asset_futs.push(asset_addr.send(AssetMessage(datetime_dictionary)));
         debug!("sent path to AssetFinder");
     }
-    let asset_result: Result<
-        Vec<Result<(HashMap<String, String>, Vec<String>), Error>>,
-        MailboxError,
-    > = try_join_all(asset_futs).await;
+    let asset_result: StageResults<PostAssets> = try_join_all(asset_futs).await;
 
     // The result is a tuple containing the original dictionary and the list of media files
 
@@ -334,9 +697,10 @@ async fn find_media(
 Finding posts is about sending each path as line to a PostFinder actor which matches the date/time information in the filename and returns a dictionary of the date/time information. This information is used to create an output directory string and path from the date/time information. The output directory is created and the dictionary is returned.
  */
 async fn find_posts(
-    arbiters: &Vec<Arbiter>,
+    arbiters: &[Arbiter],
+    config: &Arc<Config>,
     result: Vec<String>,
-) -> Result<Vec<Result<HashMap<String, String>, Error>>, MailboxError> {
+) -> StageResults<PostDates> {
     // we have the list of files, let's find the posts
     let mut post_futs: Vec<Request<PostFinder, PostFinderMessage>> = Vec::new();
     for (index, file_name) in result.iter().enumerate() {
@@ -344,13 +708,16 @@ async fn find_posts(
         let arb: &Arbiter = &arbiters[index % arbiters.len()];
         debug!("arb: {:?}", arb);
 
+        let actor_config = config.clone();
+
         let post_addr: Addr<PostFinder> =
-            PostFinder::start_in_arbiter(&arb.handle(), |_ctx| PostFinder);
+            PostFinder::start_in_arbiter(&arb.handle(), move |_ctx| PostFinder {
+                config: actor_config,
+            });
         post_futs.push(post_addr.send(PostFinderMessage(file_name.to_string())));
         debug!("sent path to PostFinder");
     }
-    let post_res: Result<Vec<Result<HashMap<String, String>, Error>>, MailboxError> =
-        try_join_all(post_futs).await;
+    let post_res: StageResults<PostDates> = try_join_all(post_futs).await;
 
     debug!("posts: {:?}", post_res);
     post_res
@@ -0,0 +1,321 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use glob::glob;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    config::Config, media_processor::ImageSize, placeholder::Placeholder, video::VideoInfo,
+};
+
+/// Name of the manifest file, kept in the root of the output directory.
+pub(crate) const MANIFEST_FILE: &str = ".vv-instagram-manifest.json";
+
+// changed when entries record something older manifests lack, so those are rebuilt
+const MANIFEST_VERSION: &str = "2";
+
+/// Record of what earlier runs built, so unchanged posts and images can be skipped.
+///
+/// Every entry stores a hash of its inputs and the files it produced. An entry is
+/// fresh when the hash still matches and all of its outputs still exist. Entries
+/// are only trusted when they were written with the same configuration.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub(crate) struct Manifest {
+    config_hash: String,
+    /// Keyed by media filename, e.g. `2023-06-01_12-30-00_UTC_1.jpg`.
+    pub media: BTreeMap<String, ManifestEntry>,
+    /// Keyed by caption filename, e.g. `2023-06-01_12-30-00_UTC.txt`.
+    pub posts: BTreeMap<String, ManifestEntry>,
+    /// Perceptual hashes of the media, keyed by media filename, so duplicate
+    /// detection only decodes new or changed images.
+    #[serde(default)]
+    pub fingerprints: BTreeMap<String, Fingerprint>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct ManifestEntry {
+    pub input_hash: String,
+    /// Paths relative to the output directory.
+    pub outputs: Vec<PathBuf>,
+    /// Placeholders of an image, kept so posts can be rendered without the media stage.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub placeholder: Option<Placeholder>,
+    /// The images written and their sizes, for the post's `srcset`, likewise.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sizes: Vec<ImageSize>,
+    /// A video's size and length, likewise.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub video: Option<VideoInfo>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct Fingerprint {
+    /// Hash of the asset and the file the perceptual hash was computed from.
+    pub input_hash: String,
+    pub hash: u64,
+    /// SHA-256 of the asset's contents alone, equal only for the very same file.
+    #[serde(default)]
+    pub content_hash: String,
+}
+
+impl Manifest {
+    /// Load the manifest from the output directory, starting afresh when there
+    /// is none, it can't be read, or the configuration has changed since.
+    pub(crate) fn load(config: &Config) -> Manifest {
+        let config_hash = config_hash(config);
+        let path = config.output.directory.join(MANIFEST_FILE);
+
+        let manifest = fs::read(&path).ok().and_then(|contents| {
+            match serde_json::from_slice::<Manifest>(&contents) {
+                Ok(manifest) => Some(manifest),
+                Err(e) => {
+                    warn!("Ignoring unreadable manifest {}: {}", path.display(), e);
+                    None
+                }
+            }
+        });
+
+        match manifest {
+            Some(manifest) if manifest.config_hash == config_hash => manifest,
+            Some(_) => {
+                info!("Configuration changed, rebuilding everything");
+                Manifest {
+                    config_hash,
+                    ..Manifest::default()
+                }
+            }
+            None => Manifest {
+                config_hash,
+                ..Manifest::default()
+            },
+        }
+    }
+
+    pub(crate) fn save(&self, config: &Config) -> Result<(), std::io::Error> {
+        let path = config.output.directory.join(MANIFEST_FILE);
+        let contents = serde_json::to_vec_pretty(self)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        fs::create_dir_all(&config.output.directory)?;
+        fs::write(&path, contents)?;
+        debug!("saved manifest {}", path.display());
+        Ok(())
+    }
+}
+
+impl ManifestEntry {
+    pub(crate) fn new(input_hash: String, outputs: Vec<PathBuf>, output_directory: &Path) -> Self {
+        let outputs = outputs
+            .into_iter()
+            .map(|output| {
+                output
+                    .strip_prefix(output_directory)
+                    .map(Path::to_path_buf)
+                    .unwrap_or(output)
+            })
+            .collect();
+        ManifestEntry {
+            input_hash,
+            outputs,
+            placeholder: None,
+            sizes: Vec::new(),
+            video: None,
+        }
+    }
+
+    pub(crate) fn is_fresh(&self, input_hash: &str, output_directory: &Path) -> bool {
+        self.input_hash == input_hash
+            && self
+                .outputs
+                .iter()
+                .all(|output| output_directory.join(output).exists())
+    }
+}
+
+/// Record a freshly built entry, deleting any outputs the previous build of it
+/// produced that are no longer part of it, e.g. a post whose slug changed.
+pub(crate) fn record(
+    entries: &mut BTreeMap<String, ManifestEntry>,
+    key: String,
+    entry: ManifestEntry,
+    output_directory: &Path,
+) {
+    let replaced: Vec<PathBuf> = entries
+        .get(&key)
+        .map(|previous| {
+            previous
+                .outputs
+                .iter()
+                .filter(|output| !entry.outputs.contains(output))
+                .cloned()
+                .collect()
+        })
+        .unwrap_or_default();
+    for output in replaced {
+        let path = output_directory.join(output);
+        match fs::remove_file(&path) {
+            Ok(_) => info!("removed outdated {}", path.display()),
+            Err(e) => debug!("unable to remove {}: {}", path.display(), e),
+        }
+    }
+    entries.insert(key, entry);
+}
+
+/// Hash the contents of the given files plus any extra strings, missing files hash as empty.
+pub(crate) fn hash_inputs(files: &[PathBuf], extra: &[&str]) -> String {
+    let mut hasher = Sha256::new();
+    for file in files {
+        hasher.update(file.to_string_lossy().as_bytes());
+        match fs::read(file) {
+            Ok(contents) => hasher.update(&contents),
+            Err(_) => hasher.update(b"\0missing"),
+        }
+    }
+    for value in extra {
+        hasher.update(value.as_bytes());
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+/// SHA-256 of a file's contents, unlike `hash_inputs` without its path.
+pub(crate) fn hash_contents(path: &Path) -> std::io::Result<String> {
+    Ok(format!("{:x}", Sha256::digest(fs::read(path)?)))
+}
+
+// everything that changes what gets written, but not which posts are selected
+fn config_hash(config: &Config) -> String {
+    let settings = format!(
+        "{:?}{:?}{:?}{:?}",
+        config.output, config.images, config.watermark, config.exif
+    );
+    let mut files = vec![config.watermark.image.clone()];
+    files.extend(config.watermark.font.clone());
+    files.extend(config.watermark.overrides.clone());
+    hash_inputs(&files, &[MANIFEST_VERSION, &settings])
+}
+
+/// Hash of all the post templates, so editing a template re-renders every post.
+pub(crate) fn templates_hash(config: &Config) -> String {
+    let mut templates: Vec<PathBuf> = glob(&config.output.templates)
+        .map(|paths| paths.filter_map(Result::ok).collect())
+        .unwrap_or_default();
+    templates.sort();
+    hash_inputs(&templates, &[])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output_directory(name: &str) -> PathBuf {
+        let directory =
+            std::env::temp_dir().join(format!("vv-manifest-{}-{}", std::process::id(), name));
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    // an entry for `input`, whose output is `image.jpg`
+    fn entry(directory: &Path, input: &Path) -> ManifestEntry {
+        let output = directory.join("image.jpg");
+        fs::write(&output, b"jpeg").unwrap();
+        ManifestEntry::new(
+            hash_inputs(&[input.to_path_buf()], &["settings"]),
+            vec![output],
+            directory,
+        )
+    }
+
+    #[test]
+    fn unchanged_entries_are_fresh() {
+        let directory = output_directory("unchanged");
+        let input = directory.join("input.jpg");
+        fs::write(&input, b"photo").unwrap();
+        let entry = entry(&directory, &input);
+        let fresh = entry.is_fresh(&hash_inputs(&[input], &["settings"]), &directory);
+        fs::remove_dir_all(&directory).unwrap();
+        assert_eq!(entry.outputs, [PathBuf::from("image.jpg")]);
+        assert!(fresh);
+    }
+
+    #[test]
+    fn changed_inputs_are_stale() {
+        let directory = output_directory("changed");
+        let input = directory.join("input.jpg");
+        fs::write(&input, b"photo").unwrap();
+        let entry = entry(&directory, &input);
+        fs::write(&input, b"edited photo").unwrap();
+        let edited = entry.is_fresh(
+            &hash_inputs(std::slice::from_ref(&input), &["settings"]),
+            &directory,
+        );
+        fs::write(&input, b"photo").unwrap();
+        let resized = entry.is_fresh(
+            &hash_inputs(std::slice::from_ref(&input), &["other"]),
+            &directory,
+        );
+        fs::remove_file(&input).unwrap();
+        let removed = entry.is_fresh(&hash_inputs(&[input], &["settings"]), &directory);
+        fs::remove_dir_all(&directory).unwrap();
+        assert!(!edited && !resized && !removed);
+    }
+
+    #[test]
+    fn missing_outputs_are_stale() {
+        let directory = output_directory("missing");
+        let input = directory.join("input.jpg");
+        fs::write(&input, b"photo").unwrap();
+        let entry = entry(&directory, &input);
+        fs::remove_file(directory.join("image.jpg")).unwrap();
+        let fresh = entry.is_fresh(&hash_inputs(&[input], &["settings"]), &directory);
+        fs::remove_dir_all(&directory).unwrap();
+        assert!(!fresh);
+    }
+
+    #[test]
+    fn changed_configuration_drops_every_entry() {
+        let directory = output_directory("config");
+        let input = directory.join("input.jpg");
+        fs::write(&input, b"photo").unwrap();
+        let mut config = Config::default();
+        config.output.directory = directory.clone();
+        let mut manifest = Manifest::load(&config);
+        manifest
+            .media
+            .insert(String::from("input.jpg"), entry(&directory, &input));
+        manifest.save(&config).unwrap();
+
+        let kept = Manifest::load(&config).media.len();
+        config.images.quality = 50;
+        let changed = Manifest::load(&config).media.len();
+        fs::remove_dir_all(&directory).unwrap();
+        assert_eq!((kept, changed), (1, 0));
+    }
+
+    #[test]
+    fn outdated_outputs_are_removed() {
+        let directory = output_directory("record");
+        let input = directory.join("input.jpg");
+        fs::write(&input, b"photo").unwrap();
+        let mut entries = BTreeMap::new();
+        let mut old = entry(&directory, &input);
+        fs::write(directory.join("old-slug.md"), b"post").unwrap();
+        old.outputs.push(PathBuf::from("old-slug.md"));
+        entries.insert(String::from("post"), old);
+
+        record(
+            &mut entries,
+            String::from("post"),
+            entry(&directory, &input),
+            &directory,
+        );
+        let removed = !directory.join("old-slug.md").exists();
+        let kept = directory.join("image.jpg").exists();
+        fs::remove_dir_all(&directory).unwrap();
+        assert!(removed && kept);
+        assert_eq!(entries["post"].outputs, [PathBuf::from("image.jpg")]);
+    }
+}
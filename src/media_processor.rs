@@ -1,9 +1,11 @@
 use std::{
     any::Any,
     collections::HashMap,
-    fs,
+    fs::{self, File},
+    io::BufWriter,
     path::{Path, PathBuf},
     process::{exit, id},
+    sync::Arc,
 };
 
 use actix::{
@@ -12,165 +14,562 @@ use actix::{
 use actix::fut::result;
 use async_std::{net::TcpListener, net::TcpStream, task};
 use chrono::NaiveDateTime;
-use dotenvy_macro::dotenv;
 use futures::executor::block_on;
 use futures::future::join_all;
 use glob::glob;
+use image::{codecs::jpeg::JpegEncoder, DynamicImage, RgbaImage};
 use lazy_static::lazy_static;
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 use photon_rs::multiple::watermark;
 use photon_rs::native::open_image;
 use photon_rs::native::save_image;
 use photon_rs::PhotonImage;
 use photon_rs::transform::{crop, resize, SamplingFilter};
 use regex::{Captures, Regex};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
-use crate::post_actor;
+use crate::{
+    config::{Config, Dimensions, ImageFormat},
+    error::Error,
+    exif_data,
+    placeholder::Placeholder,
+    thumbnail,
+    video::{self, VideoInfo},
+    watermark::{self, Watermark},
+};
+use crate::{output_target, post_actor};
 
 #[derive(Message)]
-#[rtype(result = "Result<HashMap<String, String>, std::io::Error>")]
+#[rtype(result = "Result<MediaOutput, Error>")]
 pub(crate) struct MediaMessage(pub String);
 
-pub(crate) struct MediaProcessor;
+/// What processing one asset produced.
+#[derive(Debug)]
+pub(crate) struct MediaOutput {
+    /// Paths of the written files, keyed like `image_480w.avif`.
+    pub files: HashMap<String, String>,
+    /// For a video, the placeholder of its poster.
+    pub placeholder: Option<Placeholder>,
+    /// The JPEGs of an image, or of a video's poster, narrowest first and the large image last.
+    pub sizes: Vec<ImageSize>,
+    /// A video's size and length.
+    pub video: Option<VideoInfo>,
+}
+
+/// A written image and its size.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct ImageSize {
+    pub filename: String,
+    pub width: u32,
+    pub height: u32,
+}
+
+pub(crate) struct MediaProcessor {
+    pub config: Arc<Config>,
+}
 
 impl Actor for MediaProcessor {
     type Context = Context<Self>;
 }
 
 impl Handler<MediaMessage> for MediaProcessor {
-    type Result = Result<HashMap<String, String>, std::io::Error>; // <- Message response type
+    type Result = Result<MediaOutput, Error>; // <- Message response type
 
     fn handle(&mut self, msg: MediaMessage, _ctx: &mut Context<Self>) -> Self::Result {
         let filepath = msg.0.clone();
-        let output: Vec<String> = block_on(generate_images(filepath));
-        let mut images: HashMap<String, String> = HashMap::new();
-        images.insert("image".to_string(), output[0].clone());
-        images.insert("thumbnail_image".to_string(), output[1].clone());
-        Ok(images)
+        if video::is_video(&filepath) {
+            return block_on(process_video(self.config.clone(), filepath));
+        }
+        let input_file = self.config.input.directory.join(&filepath);
+        let ((output, placeholder, sizes), thumbnail) =
+            block_on(generate_images(self.config.clone(), filepath, input_file))?;
+        let mut images: HashMap<String, String> = output.into_iter().collect();
+        images.insert("thumbnail_image".to_string(), thumbnail);
+        Ok(MediaOutput {
+            files: images,
+            placeholder: Some(placeholder),
+            sizes,
+            video: None,
+        })
     }
 }
 
-async fn generate_images(filepath: String) -> Vec<String> {
-    const INPUT_DIRECTORY: &str = dotenv!("INPUT_DIRECTORY");
+// videos are copied as they are, their poster goes through the same pipeline as a photo
+async fn process_video(config: Arc<Config>, filepath: String) -> Result<MediaOutput, Error> {
+    let input_file = config.input.directory.join(&filepath);
+    let output_directory = asset_output_directory(&config, &filepath)?;
+    let video_output = format!("{}/{}", output_directory, filepath);
+    fs::copy(&input_file, &video_output).map_err(Error::io(&input_file))?;
+    info!("Video copied successfully: {}", video_output);
+    let info = video::read_video_info(&input_file).unwrap_or_else(|e| {
+        warn!("{}", e);
+        VideoInfo::default()
+    });
+
+    let mut media: HashMap<String, String> = HashMap::new();
+    media.insert("video".to_string(), video_output);
+
+    let poster_name = poster_filename(&filepath);
+    let (poster, extracted) = match video::sidecar_poster(&config.input.directory, &filepath) {
+        Some(poster) => (poster, false),
+        None => {
+            // per process, so runs at the same time don't write over each other's poster
+            let poster =
+                std::env::temp_dir().join(format!("vv-instagram-{}-{}", id(), poster_name));
+            if let Err(e) = video::extract_poster(&input_file, &poster) {
+                // the video is still usable, the post just goes without a poster and thumbnail
+                warn!("{}", e);
+                return Ok(MediaOutput {
+                    files: media,
+                    placeholder: None,
+                    sizes: Vec::new(),
+                    video: Some(info),
+                });
+            }
+            (poster, true)
+        }
+    };
+
+    let result = generate_images(config, poster_name, poster.clone()).await;
+    if extracted {
+        fs::remove_file(&poster).ok();
+    }
+    let ((output, placeholder, sizes), thumbnail) = result?;
+    media.extend(output);
+    media.insert("thumbnail_image".to_string(), thumbnail);
+    Ok(MediaOutput {
+        files: media,
+        placeholder: Some(placeholder),
+        sizes,
+        video: Some(info),
+    })
+}
+
+/// `2023-06-01_12-30-00_UTC_thumb.jpg` for `2023-06-01_12-30-00_UTC.jpg`.
+pub(crate) fn thumbnail_filename(filepath: &str) -> String {
+    let path = Path::new(filepath);
+    format!(
+        "{}_thumb.{}",
+        path.file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or_default(),
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or_default()
+    )
+}
+
+/// The poster of `2023-06-01_12-30-00_UTC.mp4` is `2023-06-01_12-30-00_UTC.jpg`, as Instaloader names it.
+pub(crate) fn poster_filename(filepath: &str) -> String {
+    Path::new(filepath)
+        .with_extension("jpg")
+        .to_string_lossy()
+        .to_string()
+}
+
+// the large image and its narrower renditions in every format, e.g. `image_480w.avif`
+type Renditions = Vec<(String, String)>;
 
-    let mut path: PathBuf = PathBuf::from(&filepath);
-    let filename = &path.file_name().unwrap().to_str().unwrap();
-    let input_file: PathBuf = Path::new(&INPUT_DIRECTORY).join(&filename);
+// `filepath` names the outputs, `input_file` is read
+async fn generate_images(
+    config: Arc<Config>,
+    filepath: String,
+    input_file: PathBuf,
+) -> Result<((Renditions, Placeholder, Vec<ImageSize>), String), Error> {
     let infile = input_file.clone();
     let original_filepath = filepath.clone();
 
-    let tasks = vec![
-        task::spawn(process_images(original_filepath.clone(), input_file)),
-        task::spawn(process_thumbnail(original_filepath.clone(), path, infile)),
-    ];
-    // return the strings
-    join_all(tasks).await
+    let images = task::spawn(process_images(
+        config.clone(),
+        original_filepath.clone(),
+        input_file,
+    ));
+    let thumbnail = task::spawn(process_thumbnail(
+        config.clone(),
+        original_filepath.clone(),
+        infile,
+    ));
+    // return the strings, or the first error
+    futures::try_join!(images, thumbnail)
 }
 
-async fn process_images(filepath: String, input_file: PathBuf) -> String {
-    const OUTPUT_DIRECTORY: &str = dotenv!("OUTPUT_DIRECTORY");
-    const WATERMARK_IMG: &str = dotenv!("WATERMARK_IMG");
-    const LARGE_IMAGE_DIMENSIONS_WIDTH: &str = dotenv!("LARGE_IMAGE_DIMENSIONS_WIDTH");
-    const LARGE_IMAGE_DIMENSIONS_HEIGHT: &str = dotenv!("LARGE_IMAGE_DIMENSIONS_HEIGHT");
+async fn process_images(
+    config: Arc<Config>,
+    filepath: String,
+    input_file: PathBuf,
+) -> Result<(Renditions, Placeholder, Vec<ImageSize>), Error> {
+    let output_directory = asset_output_directory(&config, &filepath)?;
 
-    let dt = post_actor::make_headings_from_filepath(filepath.clone());
+    let img: PhotonImage = open(&input_file)?;
+    let exif = exif_data::output_exif(&config.exif, &input_file)?;
+    let mark = match watermark::resolve(&config, &filepath)? {
+        Some(mark_config) => Some((Watermark::load(&mark_config)?, mark_config)),
+        None => None,
+    };
 
-    let output_directory = format!("{}/img/instagram/{}", dotenv!("OUTPUT_DIRECTORY"), dt.4);
+    // resize image before watermark, so the watermark is the same size on every image
+    let (large, widths) = rendition_sizes(&config, img.get_width(), img.get_height());
+    let (large_width, large_height) = (large.width, large.height);
+    let large_image: PhotonImage =
+        if (large_width, large_height) == (img.get_width(), img.get_height()) {
+            img
+        } else {
+            resize(&img, large_width, large_height, SamplingFilter::Lanczos3)
+        };
 
-    let img_path: String = input_file.to_str().unwrap().to_string();
+    // watermark image
+    let large_image = match &mark {
+        Some((mark, mark_config)) => mark.apply(mark_config, large_image),
+        None => large_image,
+    };
+
+    let placeholder = Placeholder::from_image(&large_image)
+        .map_err(|message| Error::Image(input_file.clone(), message))?;
+
+    // the narrower renditions are scaled from the watermarked large image
+    let mut outputs: Renditions = Vec::new();
+    let mut sizes: Vec<ImageSize> = Vec::new();
+    for size in widths {
+        let rendition = resize(
+            &large_image,
+            size.width,
+            size.height,
+            SamplingFilter::Lanczos3,
+        );
+        let filename = rendition_filename(&filepath, size.width);
+        save_rendition(
+            &config,
+            rendition,
+            &output_directory,
+            &filename,
+            &format!("image_{}w", size.width),
+            exif.as_deref(),
+            &mut outputs,
+        )?;
+        sizes.push(ImageSize {
+            filename,
+            width: size.width,
+            height: size.height,
+        });
+        debug!("Rendition saved successfully: {}w", size.width);
+    }
+
+    // set up naming for large image
+    save_rendition(
+        &config,
+        large_image,
+        &output_directory,
+        &filepath,
+        "image",
+        exif.as_deref(),
+        &mut outputs,
+    )?;
+    info!("Large image saved successfully: {}", filepath);
+    sizes.push(ImageSize {
+        filename: filepath,
+        width: large_width,
+        height: large_height,
+    });
+
+    Ok((outputs, placeholder, sizes))
+}
 
-    let mut img: PhotonImage = open_image(&img_path).expect("Image should open");
-    let mark: PhotonImage = open_image(WATERMARK_IMG).expect("Watermark should open");
+// save the JPEG plus each of the configured extra formats
+fn save_rendition(
+    config: &Config,
+    img: PhotonImage,
+    output_directory: &str,
+    filename: &str,
+    key: &str,
+    exif: Option<&[u8]>,
+    outputs: &mut Renditions,
+) -> Result<(), Error> {
+    for format in config.images.formats.iter() {
+        let path = format!(
+            "{}/{}",
+            output_directory,
+            alternate_filename(filename, *format)
+        );
+        save_alternate(config, &img, *format, &path)?;
+        outputs.push((format!("{}.{}", key, format.extension()), path));
+    }
+    let path = format!("{}/{}", output_directory, filename);
+    save(img, &path, config.images.quality, exif)?;
+    outputs.push((key.to_string(), path));
+    Ok(())
+}
 
-    // watermark placement
-    // TODO: fix possible overflow error with subtraction
-    let right_placement = img.get_width() - mark.get_width() - 30_u32;
-    let bottom_placement = img.get_height() - mark.get_height() - 40_u32;
-    // resize image before watermark
-    let mut large_image: PhotonImage = resize(
-        &img,
-        LARGE_IMAGE_DIMENSIONS_WIDTH.parse().unwrap(),
-        LARGE_IMAGE_DIMENSIONS_HEIGHT.parse().unwrap(),
+async fn process_thumbnail(
+    config: Arc<Config>,
+    filepath: String,
+    infile: PathBuf,
+) -> Result<String, Error> {
+    let thumbnail_img = open(&infile)?;
+    let (crop_x, crop_y, crop_width, crop_height) = thumbnail::crop_region(
+        config.images.thumbnail_crop,
+        &thumbnail_img,
+        config.images.thumbnail,
+    );
+    debug!(
+        "thumbnail of {} cropped to {}x{} at {},{}",
+        filepath, crop_width, crop_height, crop_x, crop_y
+    );
+    // crop takes the opposite corner, not the size
+    let cropped_img = crop(
+        &thumbnail_img,
+        crop_x,
+        crop_y,
+        crop_x + crop_width,
+        crop_y + crop_height,
+    );
+
+    let result_image: PhotonImage = resize(
+        &cropped_img,
+        config.images.thumbnail.width,
+        config.images.thumbnail.height,
         SamplingFilter::Lanczos3,
     );
 
-    // watermark image
-    watermark(&mut img, &mark, right_placement, bottom_placement);
+    let output_directory = asset_output_directory(&config, &filepath)?;
 
-    // set up naming for large image
-    let output_path = format!("{}/{}", &output_directory, filepath);
+    let thumbnail_output_path = format!("{}/{}", output_directory, thumbnail_filename(&filepath));
+
+    let exif = exif_data::output_exif(&config.exif, &infile)?;
+    save(
+        result_image,
+        &thumbnail_output_path,
+        config.images.quality,
+        exif.as_deref(),
+    )?;
+    info!("Thumbnail saved successfully: {}", thumbnail_output_path);
 
-    match save_image(img, output_path.as_str()) {
-        Ok(_) => info!("Large image saved successfully: {}", output_path),
-        Err(e) => error!("Error saving image: {:?}", e),
+    Ok(thumbnail_output_path)
+}
+
+fn open(path: &Path) -> Result<PhotonImage, Error> {
+    let path_str = path
+        .to_str()
+        .ok_or_else(|| Error::Image(path.to_path_buf(), "path is not valid UTF-8".to_string()))?;
+    open_image(path_str).map_err(|e| Error::Image(path.to_path_buf(), e.to_string()))
+}
+
+// photon_rs always saves JPEGs at the image crate's default quality, so those are encoded here,
+// along with their EXIF
+fn save(img: PhotonImage, path: &str, quality: u8, exif: Option<&[u8]>) -> Result<(), Error> {
+    let image_error = |message: String| Error::Image(PathBuf::from(path), message);
+    let is_jpeg = Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("jpg") || ext.eq_ignore_ascii_case("jpeg"));
+    if !is_jpeg {
+        return save_image(img, path).map_err(|e| image_error(e.to_string()));
     }
 
-    output_path.clone().to_string()
+    let rgba = RgbaImage::from_raw(img.get_width(), img.get_height(), img.get_raw_pixels())
+        .ok_or_else(|| image_error(String::from("pixel buffer doesn't match the image size")))?;
+    let rgb = DynamicImage::ImageRgba8(rgba).to_rgb8();
+    let mut jpeg: Vec<u8> = Vec::new();
+    JpegEncoder::new_with_quality(&mut jpeg, quality)
+        .encode_image(&rgb)
+        .map_err(|e| image_error(e.to_string()))?;
+    if let Some(exif) = exif {
+        jpeg = exif_data::insert_into_jpeg(&jpeg, exif)
+            .ok_or_else(|| image_error(String::from("EXIF doesn't fit in a JPEG segment")))?;
+    }
+    fs::write(path, jpeg).map_err(Error::io(path))
 }
 
-async fn process_thumbnail(filepath: String, path: PathBuf, infile: PathBuf) -> String {
-    const THUMBNAIL_IMAGE_DIMENSIONS_WIDTH: &str = dotenv!("THUMBNAIL_IMAGE_DIMENSIONS_WIDTH");
-    const THUMBNAIL_IMAGE_DIMENSIONS_HEIGHT: &str = dotenv!("THUMBNAIL_IMAGE_DIMENSIONS_HEIGHT");
+/// Size of the large image and of each narrower rendition for a source image,
+/// widths at or above the large image's are left out as it already covers them.
+pub(crate) fn rendition_sizes(
+    config: &Config,
+    width: u32,
+    height: u32,
+) -> (Dimensions, Vec<Dimensions>) {
+    let (large_width, large_height) = fit_within(
+        width,
+        height,
+        config.images.large.width,
+        config.images.large.height,
+    );
+    let mut widths: Vec<u32> = config
+        .images
+        .widths
+        .iter()
+        .copied()
+        .filter(|width| *width < large_width)
+        .collect();
+    widths.sort_unstable();
+    widths.dedup();
 
-    const OUTPUT_DIRECTORY: &str = dotenv!("OUTPUT_DIRECTORY");
+    let renditions = widths
+        .into_iter()
+        .map(|width| Dimensions {
+            width,
+            height: ((large_height as f64 * width as f64 / large_width as f64).round() as u32)
+                .max(1),
+        })
+        .collect();
+    let large = Dimensions {
+        width: large_width,
+        height: large_height,
+    };
+    (large, renditions)
+}
 
-    let mut thumbnail_img = open_image(infile.to_str().unwrap()).expect("Image should open");
-    let mut crop_x = 0;
-    let mut crop_y = 0;
-    let mut crop_width = 0;
-    let mut crop_height = 0;
+/// `2023-06-01_12-30-00_UTC_1.jpg` at 480 pixels is `2023-06-01_12-30-00_UTC_1_480w.jpg`.
+pub(crate) fn rendition_filename(filepath: &str, width: u32) -> String {
+    let path = Path::new(filepath);
+    let file_stem = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or_default();
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some(extension) => format!("{}_{}w.{}", file_stem, width, extension),
+        None => format!("{}_{}w", file_stem, width),
+    }
+}
 
-    // Get the dimensions of the image
-    let width = thumbnail_img.get_width();
-    let height = thumbnail_img.get_height();
+// photon_rs can only write the formats of the image crate it's built with, so these use pure Rust encoders
+fn save_alternate(
+    config: &Config,
+    img: &PhotonImage,
+    format: ImageFormat,
+    path: &str,
+) -> Result<(), Error> {
+    let image_error = |message: String| Error::Image(PathBuf::from(path), message);
+    let (width, height) = (img.get_width(), img.get_height());
+    // the renditions are opaque, so the alpha channel is dropped
+    let rgb: Vec<u8> = img
+        .get_raw_pixels()
+        .chunks_exact(4)
+        .flat_map(|pixel| [pixel[0], pixel[1], pixel[2]])
+        .collect();
 
-    match width > height {
-        true => {
-            debug!("landscape");
-            let middle = height / 2;
-            crop_x = middle - (height / 2);
-            crop_width = height;
-            crop_height = height;
+    match format {
+        ImageFormat::Webp => {
+            let writer = BufWriter::new(File::create(path).map_err(Error::io(path))?);
+            image_webp::WebPEncoder::new(writer)
+                .encode(&rgb, width, height, image_webp::ColorType::Rgb8)
+                .map_err(|e| image_error(e.to_string()))
         }
-        false => {
-            debug!("portrait");
-            let middle = width / 2;
-            crop_y = middle - (width / 2);
-            crop_width = width;
-            crop_height = width;
+        ImageFormat::Avif => {
+            let pixels: Vec<ravif::RGB8> = rgb
+                .chunks_exact(3)
+                .map(|pixel| ravif::RGB8::new(pixel[0], pixel[1], pixel[2]))
+                .collect();
+            let encoded = ravif::Encoder::new()
+                .with_quality(config.images.avif_quality as f32)
+                .with_speed(config.images.avif_speed)
+                .encode_rgb(ravif::Img::new(&pixels, width as usize, height as usize))
+                .map_err(|e| image_error(e.to_string()))?;
+            fs::write(path, encoded.avif_file).map_err(Error::io(path))
         }
     }
-    let mut cropped_img = crop(&mut thumbnail_img, crop_x, crop_y, crop_width, crop_height);
+}
 
-    let result_image: PhotonImage = resize(
-        &cropped_img,
-        THUMBNAIL_IMAGE_DIMENSIONS_WIDTH.parse().unwrap(),
-        THUMBNAIL_IMAGE_DIMENSIONS_HEIGHT.parse().unwrap(),
-        SamplingFilter::Nearest,
+/// `2023-06-01_12-30-00_UTC_1_480w.jpg` as AVIF is `2023-06-01_12-30-00_UTC_1_480w.avif`.
+pub(crate) fn alternate_filename(filename: &str, format: ImageFormat) -> String {
+    Path::new(filename)
+        .with_extension(format.extension())
+        .to_string_lossy()
+        .to_string()
+}
+
+/// Largest size with the same aspect ratio as `width`×`height` that fits within
+/// `max_width`×`max_height`. Images that already fit are left at their own size.
+fn fit_within(width: u32, height: u32, max_width: u32, max_height: u32) -> (u32, u32) {
+    if width <= max_width && height <= max_height {
+        return (width, height);
+    }
+    let scale = f64::min(
+        max_width as f64 / width as f64,
+        max_height as f64 / height as f64,
     );
+    (
+        ((width as f64 * scale).round() as u32).clamp(1, max_width),
+        ((height as f64 * scale).round() as u32).clamp(1, max_height),
+    )
+}
 
-    let dt = post_actor::make_headings_from_filepath(filepath.clone());
-    let output_directory = format!("{}/img/instagram/{}", dotenv!("OUTPUT_DIRECTORY"), dt.4);
+// where the output target wants this asset's renditions written
+fn asset_output_directory(config: &Config, filepath: &str) -> Result<String, Error> {
+    let post_stem = post_actor::post_stem_from_asset(filepath);
+    let post_datetime = post_actor::get_datetime_from_string(post_stem)?;
+    let target = output_target::from_config(config);
+    Ok(config
+        .output
+        .directory
+        .join(target.asset_directory(post_stem, &post_datetime))
+        .to_string_lossy()
+        .to_string())
+}
 
-    // // set up naming for thumbnail image
-    let file_stem = Path::new(&filepath).file_stem().unwrap().to_str().unwrap();
-    let extension = Path::new(&filepath).extension().unwrap().to_str().unwrap();
-    let parent = path.parent().unwrap().to_str().unwrap();
-    let thumbnail_output_path = format!(
-        "{}/{}_thumb.{}",
-        output_directory, file_stem, extension
-    );
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    match save_image(result_image, &thumbnail_output_path) {
-        Ok(_) => info!("Thumbnail saved successfully: {}", thumbnail_output_path),
-        Err(e) => error!("Error saving thumbnail: {:?}", e),
+    #[test]
+    fn small_images_are_not_upscaled() {
+        assert_eq!(fit_within(800, 600, 2048, 2048), (800, 600));
+        assert_eq!(fit_within(2048, 2048, 2048, 2048), (2048, 2048));
     }
 
-    Path::new(&thumbnail_output_path)
-        .file_name()
-        .unwrap()
-        .to_str()
-        .unwrap()
-        .to_string()
+    #[test]
+    fn landscape_fits_the_width() {
+        assert_eq!(fit_within(4000, 3000, 2048, 2048), (2048, 1536));
+        assert_eq!(fit_within(3000, 1000, 1200, 800), (1200, 400));
+    }
+
+    #[test]
+    fn portrait_fits_the_height() {
+        assert_eq!(fit_within(3000, 4000, 2048, 2048), (1536, 2048));
+        assert_eq!(fit_within(1080, 1920, 1200, 800), (450, 800));
+    }
+
+    #[test]
+    fn slivers_keep_a_pixel() {
+        assert_eq!(fit_within(10000, 2, 100, 100), (100, 1));
+    }
+
+    fn sizes(widths: &[u32], width: u32, height: u32) -> ((u32, u32), Vec<(u32, u32)>) {
+        let mut config = Config::default();
+        config.images.large = Dimensions {
+            width: 1200,
+            height: 1200,
+        };
+        config.images.widths = widths.to_vec();
+        let (large, renditions) = rendition_sizes(&config, width, height);
+        (
+            (large.width, large.height),
+            renditions.iter().map(|r| (r.width, r.height)).collect(),
+        )
+    }
+
+    #[test]
+    fn widths_are_sorted_and_deduplicated() {
+        let (_, renditions) = sizes(&[960, 480, 960, 480], 2400, 1600);
+        assert_eq!(renditions, [(480, 320), (960, 640)]);
+    }
+
+    #[test]
+    fn no_rendition_is_as_wide_as_the_large_image() {
+        let (large, renditions) = sizes(&[480, 960, 1200, 1440], 2400, 1600);
+        assert_eq!(large, (1200, 800));
+        assert_eq!(renditions, [(480, 320), (960, 640)]);
+    }
+
+    #[test]
+    fn small_sources_are_not_upscaled() {
+        let (large, renditions) = sizes(&[480, 960, 1440], 640, 480);
+        assert_eq!(large, (640, 480));
+        assert_eq!(renditions, [(480, 360)]);
+    }
+
+    #[test]
+    fn portrait_renditions_keep_the_aspect_ratio() {
+        let (large, renditions) = sizes(&[300, 480, 960], 1080, 1920);
+        assert_eq!(large, (675, 1200));
+        assert_eq!(renditions, [(300, 533), (480, 853)]);
+    }
 }
@@ -0,0 +1,207 @@
+use std::{
+    fs::File,
+    io::{BufReader, Read},
+    path::{Path, PathBuf},
+};
+
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+/// The parts of Instaloader's `<date>_UTC.json.xz` sidecar that are useful on the blog.
+#[derive(Serialize, Debug, Clone, Default)]
+pub(crate) struct PostMetadata {
+    pub shortcode: String,
+    /// Link back to the post on instagram.com.
+    pub url: String,
+    pub likes: Option<u64>,
+    pub comments: Option<u64>,
+    pub location: Option<Location>,
+    pub owner: Option<Owner>,
+    pub is_video: bool,
+    /// Length in seconds when the post is a single video.
+    pub video_duration: Option<f64>,
+    pub accessibility_caption: Option<String>,
+    /// Carousel items in the order Instagram shows them, empty for single media posts.
+    pub children: Vec<ChildMetadata>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub(crate) struct Location {
+    pub id: Option<String>,
+    pub name: String,
+    pub slug: Option<String>,
+    pub lat: Option<f64>,
+    pub lng: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(default)]
+pub(crate) struct Owner {
+    pub id: Option<String>,
+    pub username: String,
+    pub full_name: Option<String>,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub(crate) struct ChildMetadata {
+    pub shortcode: Option<String>,
+    pub is_video: bool,
+    pub video_duration: Option<f64>,
+    pub accessibility_caption: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+// Instaloader writes the GraphQL node it received, wrapped in {"node": ..., "instaloader": ...}
+#[derive(Deserialize, Debug)]
+struct RawPost {
+    node: RawNode,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+struct RawNode {
+    shortcode: Option<String>,
+    edge_media_preview_like: Option<RawCount>,
+    edge_liked_by: Option<RawCount>,
+    edge_media_to_comment: Option<RawCount>,
+    location: Option<Location>,
+    owner: Option<Owner>,
+    is_video: bool,
+    video_duration: Option<f64>,
+    accessibility_caption: Option<String>,
+    dimensions: Option<RawDimensions>,
+    edge_sidecar_to_children: Option<RawEdges>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+struct RawCount {
+    count: Option<u64>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+struct RawDimensions {
+    width: Option<u32>,
+    height: Option<u32>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+struct RawEdges {
+    edges: Vec<RawEdge>,
+}
+
+#[derive(Deserialize, Debug)]
+struct RawEdge {
+    node: RawNode,
+}
+
+#[derive(Debug)]
+pub(crate) enum MetadataError {
+    Io(std::io::Error),
+    Decompress(lzma_rs::error::Error),
+    Json(serde_json::Error),
+}
+
+impl std::fmt::Display for MetadataError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MetadataError::Io(e) => write!(f, "{}", e),
+            MetadataError::Decompress(e) => write!(f, "unable to decompress: {:?}", e),
+            MetadataError::Json(e) => write!(f, "unable to parse: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for MetadataError {}
+
+/// Path of the metadata sidecar for a post, preferring the compressed `.json.xz`
+/// Instaloader writes by default over a plain `.json` from `--no-compress-json`.
+pub(crate) fn metadata_path(input_directory: &Path, post_stem: &str) -> Option<PathBuf> {
+    [
+        input_directory.join(format!("{}.json.xz", post_stem)),
+        input_directory.join(format!("{}.json", post_stem)),
+    ]
+    .into_iter()
+    .find(|path| path.is_file())
+}
+
+/// Read the metadata sidecar for a post, `None` when Instaloader didn't write one.
+///
+/// A sidecar that exists but can't be read is logged and treated as missing,
+/// the caption and media are enough to build the post.
+pub(crate) fn find_metadata(input_directory: &Path, post_stem: &str) -> Option<PostMetadata> {
+    let path = metadata_path(input_directory, post_stem)?;
+    match read_metadata(&path) {
+        Ok(metadata) => {
+            debug!("metadata for {}: {:?}", post_stem, metadata);
+            Some(metadata)
+        }
+        Err(e) => {
+            warn!("Ignoring metadata {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+pub(crate) fn read_metadata(path: &Path) -> Result<PostMetadata, MetadataError> {
+    let mut reader = BufReader::new(File::open(path).map_err(MetadataError::Io)?);
+    let mut json: Vec<u8> = Vec::new();
+
+    if path.extension().is_some_and(|ext| ext == "xz") {
+        lzma_rs::xz_decompress(&mut reader, &mut json).map_err(MetadataError::Decompress)?;
+    } else {
+        reader.read_to_end(&mut json).map_err(MetadataError::Io)?;
+    }
+
+    let raw: RawPost = serde_json::from_slice(&json).map_err(MetadataError::Json)?;
+    Ok(PostMetadata::from(raw.node))
+}
+
+impl From<RawNode> for PostMetadata {
+    fn from(node: RawNode) -> Self {
+        let shortcode = node.shortcode.unwrap_or_default();
+        let likes = node
+            .edge_media_preview_like
+            .and_then(|edge| edge.count)
+            .or_else(|| node.edge_liked_by.and_then(|edge| edge.count));
+
+        let children = node
+            .edge_sidecar_to_children
+            .map(|children| children.edges)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|edge| ChildMetadata::from(edge.node))
+            .collect();
+
+        PostMetadata {
+            url: format!("https://www.instagram.com/p/{}/", shortcode),
+            shortcode,
+            likes,
+            comments: node.edge_media_to_comment.and_then(|edge| edge.count),
+            location: node.location,
+            owner: node.owner,
+            is_video: node.is_video,
+            video_duration: node.video_duration,
+            accessibility_caption: node.accessibility_caption,
+            children,
+        }
+    }
+}
+
+impl From<RawNode> for ChildMetadata {
+    fn from(node: RawNode) -> Self {
+        let dimensions = node.dimensions.unwrap_or_default();
+        ChildMetadata {
+            shortcode: node.shortcode,
+            is_video: node.is_video,
+            video_duration: node.video_duration,
+            accessibility_caption: node.accessibility_caption,
+            width: dimensions.width,
+            height: dimensions.height,
+        }
+    }
+}
@@ -0,0 +1,348 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{config::Config, error::Error, Post};
+
+/// The static site generators posts can be written for.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum TargetKind {
+    #[default]
+    Hexo,
+    Hugo,
+    Jekyll,
+    Zola,
+    Eleventy,
+}
+
+/// Where a site generator expects posts and their media, and how it wants front matter.
+///
+/// All paths are relative to the output directory. `post_stem` is the Instaloader
+/// name of the post, e.g. `2023-06-01_12-30-00_UTC`, which keeps every path unique;
+/// `slug` is the readable part derived from the caption.
+pub(crate) trait OutputTarget: Send + Sync {
+    /// The Markdown file for a post.
+    fn post_path(&self, post_stem: &str, date: &NaiveDateTime, slug: &str) -> PathBuf;
+
+    /// The directory a post's images and videos are written to.
+    fn asset_directory(&self, post_stem: &str, date: &NaiveDateTime) -> PathBuf;
+
+    /// The URL a post's media file is served from.
+    fn asset_url(&self, post_stem: &str, date: &NaiveDateTime, filename: &str) -> String;
+
+    /// The front matter block, including its delimiters.
+    fn front_matter(&self, post: &Post) -> Result<String, Error>;
+
+    /// Glob patterns matching every file this target generates, used by `clean`.
+    fn generated_files(&self) -> Vec<String>;
+
+    /// Whether a post can show another post's media, not so when media URLs are
+    /// relative to the page.
+    fn shares_assets(&self) -> bool {
+        true
+    }
+
+    /// Create the directories for a post, plus anything else the site needs.
+    fn create_directories(
+        &self,
+        output_directory: &Path,
+        post_stem: &str,
+        date: &NaiveDateTime,
+    ) -> Result<(), std::io::Error> {
+        fs::create_dir_all(output_directory.join(self.asset_directory(post_stem, date)))?;
+        if let Some(parent) = self.post_path(post_stem, date, "").parent() {
+            fs::create_dir_all(output_directory.join(parent))?;
+        }
+        Ok(())
+    }
+}
+
+/// The output target selected in the configuration.
+pub(crate) fn from_config(config: &Config) -> Box<dyn OutputTarget> {
+    let section = config.output.section.clone();
+    match config.output.target {
+        TargetKind::Hexo => Box::new(Hexo),
+        TargetKind::Hugo => Box::new(Hugo { section }),
+        TargetKind::Jekyll => Box::new(Jekyll),
+        TargetKind::Zola => Box::new(Zola { section }),
+        TargetKind::Eleventy => Box::new(Eleventy { section }),
+    }
+}
+
+/// `YYYY/MM/DD/slug-stem.md` with media under `img/instagram/YYYY/MM/DD/`.
+pub(crate) struct Hexo;
+
+impl OutputTarget for Hexo {
+    fn post_path(&self, post_stem: &str, date: &NaiveDateTime, slug: &str) -> PathBuf {
+        PathBuf::from(format!(
+            "{}/{}-{}.md",
+            date.format("%Y/%m/%d"),
+            slug,
+            post_stem
+        ))
+    }
+
+    fn asset_directory(&self, _post_stem: &str, date: &NaiveDateTime) -> PathBuf {
+        PathBuf::from(format!("img/instagram/{}", date.format("%Y/%m/%d")))
+    }
+
+    fn asset_url(&self, post_stem: &str, date: &NaiveDateTime, filename: &str) -> String {
+        format!(
+            "/{}/{}",
+            self.asset_directory(post_stem, date).display(),
+            filename
+        )
+    }
+
+    fn front_matter(&self, post: &Post) -> Result<String, Error> {
+        Ok(yaml_front_matter(&json!({
+            "title": post.title,
+            "date": format!("{} {}", post.date, post.time_heading),
+            "thumbnail": post.thumbnail_image,
+            "categories": post.categories,
+            "tags": post.tags,
+        })))
+    }
+
+    fn generated_files(&self) -> Vec<String> {
+        vec![
+            String::from("[0-9][0-9][0-9][0-9]/[0-9][0-9]/[0-9][0-9]/*_UTC.md"),
+            String::from("img/instagram/**/*"),
+        ]
+    }
+}
+
+/// Page bundles: `content/<section>/<stem>/index.md` with the media alongside.
+pub(crate) struct Hugo {
+    section: String,
+}
+
+impl OutputTarget for Hugo {
+    fn post_path(&self, post_stem: &str, date: &NaiveDateTime, _slug: &str) -> PathBuf {
+        self.asset_directory(post_stem, date).join("index.md")
+    }
+
+    fn asset_directory(&self, post_stem: &str, _date: &NaiveDateTime) -> PathBuf {
+        PathBuf::from(format!("content/{}/{}", self.section, post_stem))
+    }
+
+    // bundle resources are addressed relative to the page
+    fn asset_url(&self, _post_stem: &str, _date: &NaiveDateTime, filename: &str) -> String {
+        filename.to_string()
+    }
+
+    fn shares_assets(&self) -> bool {
+        false
+    }
+
+    fn front_matter(&self, post: &Post) -> Result<String, Error> {
+        Ok(yaml_front_matter(&json!({
+            "title": post.title,
+            "date": format!("{}T{}Z", post.date, post.time_heading),
+            "slug": post.slug,
+            "images": [post.thumbnail_image],
+            "categories": post.categories,
+            "tags": post.tags,
+        })))
+    }
+
+    fn generated_files(&self) -> Vec<String> {
+        vec![format!("content/{}/*_UTC/**/*", self.section)]
+    }
+}
+
+/// `_posts/YYYY-MM-DD-slug-stem.md` with media under `assets/img/instagram/YYYY/MM/DD/`.
+pub(crate) struct Jekyll;
+
+impl OutputTarget for Jekyll {
+    fn post_path(&self, post_stem: &str, date: &NaiveDateTime, slug: &str) -> PathBuf {
+        PathBuf::from(format!(
+            "_posts/{}-{}-{}.md",
+            date.format("%Y-%m-%d"),
+            slug,
+            post_stem
+        ))
+    }
+
+    fn asset_directory(&self, _post_stem: &str, date: &NaiveDateTime) -> PathBuf {
+        PathBuf::from(format!("assets/img/instagram/{}", date.format("%Y/%m/%d")))
+    }
+
+    fn asset_url(&self, post_stem: &str, date: &NaiveDateTime, filename: &str) -> String {
+        format!(
+            "/{}/{}",
+            self.asset_directory(post_stem, date).display(),
+            filename
+        )
+    }
+
+    // the slug keeps the post stem out of the permalink
+    fn front_matter(&self, post: &Post) -> Result<String, Error> {
+        Ok(yaml_front_matter(&json!({
+            "layout": "post",
+            "title": post.title,
+            "date": format!("{} {} +0000", post.date, post.time_heading),
+            "slug": post.slug,
+            "image": post.thumbnail_image,
+            "categories": post.categories,
+            "tags": post.tags,
+        })))
+    }
+
+    fn generated_files(&self) -> Vec<String> {
+        vec![
+            String::from("_posts/*_UTC.md"),
+            String::from("assets/img/instagram/**/*"),
+        ]
+    }
+}
+
+/// Colocated assets: `content/<section>/<stem>/index.md` with TOML front matter.
+pub(crate) struct Zola {
+    section: String,
+}
+
+impl OutputTarget for Zola {
+    fn post_path(&self, post_stem: &str, date: &NaiveDateTime, _slug: &str) -> PathBuf {
+        self.asset_directory(post_stem, date).join("index.md")
+    }
+
+    fn asset_directory(&self, post_stem: &str, _date: &NaiveDateTime) -> PathBuf {
+        PathBuf::from(format!("content/{}/{}", self.section, post_stem))
+    }
+
+    fn asset_url(&self, _post_stem: &str, _date: &NaiveDateTime, filename: &str) -> String {
+        filename.to_string()
+    }
+
+    fn shares_assets(&self) -> bool {
+        false
+    }
+
+    fn front_matter(&self, post: &Post) -> Result<String, Error> {
+        #[derive(Serialize)]
+        struct FrontMatter<'a> {
+            title: &'a str,
+            // a TOML datetime rather than a string, so Zola can sort by it
+            date: toml::value::Datetime,
+            slug: &'a str,
+            taxonomies: Taxonomies<'a>,
+            extra: Extra<'a>,
+        }
+        #[derive(Serialize)]
+        struct Taxonomies<'a> {
+            categories: &'a [String],
+            tags: &'a [String],
+        }
+        #[derive(Serialize)]
+        struct Extra<'a> {
+            thumbnail: &'a str,
+        }
+
+        let front_matter = FrontMatter {
+            title: &post.title,
+            date: format!("{}T{}Z", post.date, post.time_heading)
+                .parse()
+                .map_err(|e| Error::Template(format!("front matter date: {}", e)))?,
+            slug: &post.slug,
+            taxonomies: Taxonomies {
+                categories: &post.categories,
+                tags: &post.tags,
+            },
+            extra: Extra {
+                thumbnail: &post.thumbnail_image,
+            },
+        };
+        let front_matter = toml::to_string(&front_matter)
+            .map_err(|e| Error::Template(format!("front matter: {}", e)))?;
+        Ok(format!("+++\n{}+++\n", front_matter))
+    }
+
+    fn generated_files(&self) -> Vec<String> {
+        vec![format!("content/{}/*_UTC/**/*", self.section)]
+    }
+
+    // a directory only becomes a Zola section once it has an _index.md
+    fn create_directories(
+        &self,
+        output_directory: &Path,
+        post_stem: &str,
+        date: &NaiveDateTime,
+    ) -> Result<(), std::io::Error> {
+        fs::create_dir_all(output_directory.join(self.asset_directory(post_stem, date)))?;
+        let section_index = output_directory.join(format!("content/{}/_index.md", self.section));
+        if !section_index.exists() {
+            fs::write(
+                section_index,
+                format!("+++\ntitle = {:?}\nsort_by = \"date\"\n+++\n", self.section),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// `<section>/YYYY/MM/DD/slug-stem.md` with media under `img/instagram/YYYY/MM/DD/`.
+pub(crate) struct Eleventy {
+    section: String,
+}
+
+impl OutputTarget for Eleventy {
+    fn post_path(&self, post_stem: &str, date: &NaiveDateTime, slug: &str) -> PathBuf {
+        PathBuf::from(format!(
+            "{}/{}/{}-{}.md",
+            self.section,
+            date.format("%Y/%m/%d"),
+            slug,
+            post_stem
+        ))
+    }
+
+    fn asset_directory(&self, _post_stem: &str, date: &NaiveDateTime) -> PathBuf {
+        PathBuf::from(format!("img/instagram/{}", date.format("%Y/%m/%d")))
+    }
+
+    fn asset_url(&self, post_stem: &str, date: &NaiveDateTime, filename: &str) -> String {
+        format!(
+            "/{}/{}",
+            self.asset_directory(post_stem, date).display(),
+            filename
+        )
+    }
+
+    // Eleventy builds its collections from tags, so the categories join them
+    fn front_matter(&self, post: &Post) -> Result<String, Error> {
+        let mut tags: Vec<String> = post.categories.clone();
+        tags.extend(post.tags.iter().cloned());
+        Ok(yaml_front_matter(&json!({
+            "title": post.title,
+            "date": format!("{}T{}Z", post.date, post.time_heading),
+            "thumbnail": post.thumbnail_image,
+            "tags": tags,
+        })))
+    }
+
+    fn generated_files(&self) -> Vec<String> {
+        vec![
+            format!("{}/**/*_UTC.md", self.section),
+            String::from("img/instagram/**/*"),
+        ]
+    }
+}
+
+// JSON scalars and flow sequences are valid YAML, which saves pulling in a YAML serializer
+fn yaml_front_matter(fields: &Value) -> String {
+    let mut front_matter = String::from("---\n");
+    if let Value::Object(map) = fields {
+        for (key, value) in map {
+            front_matter.push_str(&format!("{}: {}\n", key, value));
+        }
+    }
+    front_matter.push_str("---\n");
+    front_matter
+}
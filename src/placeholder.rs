@@ -0,0 +1,89 @@
+use std::collections::HashMap;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use image::{codecs::jpeg::JpegEncoder, imageops, DynamicImage, RgbaImage};
+use photon_rs::PhotonImage;
+use serde::{Deserialize, Serialize};
+
+// blurhash is computed from a copy this wide, it only describes a few colour gradients anyway
+const SAMPLE_WIDTH: u32 = 32;
+// width of the inline preview, blurred up by the browser
+const LQIP_WIDTH: u32 = 16;
+const LQIP_QUALITY: u8 = 40;
+
+/// What a page shows while the real image is loading.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct Placeholder {
+    pub blurhash: String,
+    /// A tiny JPEG as a `data:` URL, for `src` or a CSS background.
+    pub lqip: String,
+    /// The most common colour, `#rrggbb`.
+    pub color: String,
+}
+
+impl Placeholder {
+    /// Compute the placeholders of an image that's already decoded.
+    pub(crate) fn from_image(img: &PhotonImage) -> Result<Placeholder, String> {
+        let rgba = RgbaImage::from_raw(img.get_width(), img.get_height(), img.get_raw_pixels())
+            .ok_or_else(|| String::from("pixel buffer doesn't match the image size"))?;
+        let sample = shrink(&rgba, SAMPLE_WIDTH);
+
+        // more components along the longer side keep the gradients roughly square
+        let (components_x, components_y) = match sample.width() >= sample.height() {
+            true => (4, 3),
+            false => (3, 4),
+        };
+        let blurhash = blurhash::encode(
+            components_x,
+            components_y,
+            sample.width(),
+            sample.height(),
+            sample.as_raw(),
+        )
+        .map_err(|e| format!("unable to compute blurhash: {:?}", e))?;
+
+        let preview = DynamicImage::ImageRgba8(shrink(&rgba, LQIP_WIDTH)).to_rgb8();
+        let mut jpeg: Vec<u8> = Vec::new();
+        JpegEncoder::new_with_quality(&mut jpeg, LQIP_QUALITY)
+            .encode_image(&preview)
+            .map_err(|e| format!("unable to encode preview: {}", e))?;
+
+        Ok(Placeholder {
+            blurhash,
+            lqip: format!("data:image/jpeg;base64,{}", STANDARD.encode(jpeg)),
+            color: dominant_color(&sample),
+        })
+    }
+}
+
+fn shrink(img: &RgbaImage, width: u32) -> RgbaImage {
+    let width = width.min(img.width());
+    let height = ((img.height() as u64 * width as u64 / img.width() as u64) as u32).max(1);
+    imageops::resize(img, width, height, imageops::FilterType::Triangle)
+}
+
+// the average of the most populated bucket, with each channel cut to 4 bits, so that
+// a large area of similar colour wins over an average of everything
+fn dominant_color(img: &RgbaImage) -> String {
+    let mut buckets: HashMap<[u8; 3], (u32, [u32; 3])> = HashMap::new();
+    for pixel in img.pixels() {
+        let key = [pixel[0] >> 4, pixel[1] >> 4, pixel[2] >> 4];
+        let (count, sum) = buckets.entry(key).or_insert((0, [0; 3]));
+        *count += 1;
+        for (total, value) in sum.iter_mut().zip(pixel.0) {
+            *total += value as u32;
+        }
+    }
+    // ties are broken by the bucket's colour, so the result doesn't depend on the map's order
+    let (count, sum) = buckets
+        .into_iter()
+        .max_by_key(|(key, (count, _))| (*count, *key))
+        .map(|(_, bucket)| bucket)
+        .unwrap_or((1, [0; 3]));
+    format!(
+        "#{:02x}{:02x}{:02x}",
+        sum[0] / count,
+        sum[1] / count,
+        sum[2] / count
+    )
+}
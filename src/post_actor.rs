@@ -1,29 +1,52 @@
 use std::{
     any::Any,
+    cmp::Ordering,
     collections::HashMap,
     fs,
     path::{Path, PathBuf},
     process::{exit, id},
+    sync::{Arc, OnceLock},
 };
 
 use actix::{
     Actor, Context, ContextFutureSpawner, Handler, Message, ResponseActFuture, System, WrapFuture,
 };
 use chrono::NaiveDateTime;
-use dotenvy_macro::dotenv;
 use lazy_static::lazy_static;
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 use regex::Regex;
 use tera::Tera;
 
-use crate::{instagram, Post};
+use crate::{
+    alt_text::AltText,
+    asset_finder,
+    config::{Config, ImageFormat},
+    error::Error,
+    exif_data, instagram,
+    manifest::ManifestEntry,
+    media_processor::{self, ImageSize},
+    metadata,
+    output_target::{self, OutputTarget},
+    tags::{self, Taxonomy},
+    title, video, ImageAlternate, ImageSource, MediaType, Orientation, Post, PostImage,
+};
 
 #[derive(Message)]
-#[rtype(result = "Result<(), std::io::Error>")]
-
-pub(crate) struct PostMessage(pub String, pub Vec<String>);
-
-pub(crate) struct PostActor;
+#[rtype(result = "Result<String, Error>")]
+pub(crate) struct PostMessage(
+    pub String,
+    pub Vec<String>,
+    /// What the media stage recorded of the post's assets, by asset filename.
+    pub HashMap<String, ManifestEntry>,
+    /// Assets that duplicate an earlier post's, mapped to the asset whose output they use.
+    pub HashMap<String, String>,
+    /// The post's slug, made unique among all the posts.
+    pub Option<String>,
+);
+
+pub(crate) struct PostActor {
+    pub config: Arc<Config>,
+}
 
 impl Actor for PostActor {
     type Context = Context<Self>;
@@ -39,214 +62,356 @@ impl Actor for PostActor {
 }
 
 impl Handler<PostMessage> for PostActor {
-    type Result = Result<(), std::io::Error>; // <- Message response type
+    type Result = Result<String, Error>; // <- Message response type
 
     fn handle(&mut self, msg: PostMessage, _ctx: &mut Context<Self>) -> Self::Result {
-        const OUTPUT_DIRECTORY: &str = dotenv!("OUTPUT_DIRECTORY");
-
         debug!("Going to render post: {}", msg.0.as_str());
         debug!("Post {} has assets: {:?}", msg.0.as_str(), msg.1);
 
-        let post = convert_post(msg.0.as_str(), msg.1);
+        let post = convert_post(&self.config, msg.0.as_str(), msg.1, &msg.2, &msg.3, msg.4)?;
         let output_path = post.filename.clone();
         info!("Post: {:?}", post);
-        let rendered = render_template(post);
-        write_file(&output_path, rendered);
-        Ok(())
+        let rendered = render_template(&self.config, post)?;
+        write_file(&self.config, &output_path, rendered)
     }
 }
 
-fn render_template(post: Post) -> String {
-    lazy_static! {
-        static ref TEMPLATES: Tera = {
-            let mut tera = match Tera::new("templates/**/*.md") {
-                Ok(t) => t,
-                Err(e) => {
-                    println!("Parsing error(s): {}", e);
-                    exit(1);
-                }
-            };
+fn render_template(config: &Config, post: Post) -> Result<String, Error> {
+    // the template glob comes from the runtime config, so the templates are parsed on first use
+    // a parse error is kept (as text, tera::Error isn't Clone) and reported for every post
+    static TEMPLATES: OnceLock<Result<Tera, String>> = OnceLock::new();
+
+    let templates = TEMPLATES
+        .get_or_init(|| {
+            let mut tera =
+                Tera::new(&config.output.templates).map_err(|e| Error::from(e).to_string())?;
             tera.autoescape_on(vec![".html", ".sql"]);
             // tera.register_filter("do_nothing", do_nothing_filter);
-            tera
-        };
-    }
+            Ok(tera)
+        })
+        .as_ref()
+        .map_err(|e| Error::Template(e.clone()))?;
 
-    match &tera::Context::from_serialize(&post) {
-        Ok(t) => debug!("Context: {:?}", &tera::Context::from_serialize(&post)),
-        Err(e) => error!("Error: {:?}", e),
-    }
+    let context = tera::Context::from_serialize(&post)?;
+    debug!("Context: {:?}", context);
 
-    let output = TEMPLATES.render(
-        "001_post.md",
-        &tera::Context::from_serialize(&post).unwrap(),
-    );
+    let output = templates.render(&config.output.template, &context)?;
 
     debug!("Output: {:?}", output);
-    return output.unwrap();
+    Ok(output)
 }
 
-fn write_file(output_path: &str, rendered: String) -> Result<(), std::io::Error> {
-    const OUTPUT_DIRECTORY: &str = dotenv!("OUTPUT_DIRECTORY");
-    let output_file_path = format!("{}/{}", OUTPUT_DIRECTORY, output_path);
+// returns the path of the written file
+fn write_file(config: &Config, output_path: &str, rendered: String) -> Result<String, Error> {
+    let output_file_path = format!("{}/{}", config.output.directory.display(), output_path);
     debug!("output_file_path: {}", output_file_path);
-    // fs::create_dir_all(Path::new(&output_file_path).parent().unwrap())?;
-    fs::write(output_file_path, rendered).expect("Unable to write file");
-    Ok(())
+    if let Some(parent) = Path::new(&output_file_path).parent() {
+        fs::create_dir_all(parent).map_err(Error::io(parent))?;
+    }
+    fs::write(&output_file_path, rendered).map_err(Error::io(&output_file_path))?;
+    Ok(output_file_path)
 }
 
-fn convert_post(post_name: &str, asset_list: Vec<String>) -> Post {
-    const INPUT_DIRECTORY: &str = dotenv!("INPUT_DIRECTORY");
-
+fn convert_post(
+    config: &Config,
+    post_name: &str,
+    asset_list: Vec<String>,
+    media: &HashMap<String, ManifestEntry>,
+    reused: &HashMap<String, String>,
+    slug: Option<String>,
+) -> Result<Post, Error> {
     // read post text file
-    let post_file_path = format!("{}/{}", INPUT_DIRECTORY, post_name);
+    let post_file_path = format!("{}/{}", config.input.directory.display(), post_name);
 
     // we need the datetime portion of the file path
     debug!("post_file_path: {}", post_file_path);
 
     let mut post_file_contents: String =
-        fs::read_to_string(&post_file_path).expect("Something went wrong reading the file");
+        fs::read_to_string(&post_file_path).map_err(Error::io(&post_file_path))?;
 
+    let caption = post_file_contents.clone();
     post_file_contents = instagram::match_and_replace_usernames(&post_file_contents).to_string();
 
-    let hashtags_result = instagram::find_hashtags(&post_file_contents);
-
-    let tags: Vec<String> = hashtags_result
-        .iter()
-        .map(|m| m.as_str().to_string())
-        .map(|s| s.strip_prefix("#").unwrap_or(&s).to_string())
-        .collect();
-
-    // if title_prefix is not blank, add a space and a pipe at the end
-    let mut title_prefix = match tags.get(0) {
-        Some(s) => format!("{} | ", s.as_str()),
-        None => String::from(""),
-    };
-
-    // we need a filename prefix, taken from the post text
-    if title_prefix.is_empty() {
-        // get the first two words of the post
-        let words: Vec<&str> = post_file_contents.split_whitespace().collect();
-        let first_two_words: Vec<String> = words
-            .into_iter()
-            .take(2)
-            .map(|word| {
-                word.chars()
-                    .filter(|ch| !ch.is_ascii_punctuation())
-                    .collect()
-            })
-            .collect();
-        title_prefix = first_two_words.join(" ")
-    } else {
-        // get the first four words of the post plus the first hashtag, put into a Vec
-        // let words: Vec<&str> = post_file_contents.split_whitespace().collect();
-        title_prefix = tags[0].clone();
-    }
-
-    // we want to parameterize the prefix and replace special characters with pretty url-friendly characters
-    title_prefix = unidecode::unidecode(&title_prefix).to_lowercase();
-    let chars_to_replace = vec![
-        ',', ' ', '!', '.', ':', '?', '#', '@', '(', ')', '=', '\'', '"',
-    ];
-
-    for ch in chars_to_replace {
-        title_prefix = title_prefix.replace(ch, "-");
-    }
-    // remove any double hyphens
-    title_prefix = title_prefix.replace("--", "-");
+    // the mapping file decides what the hashtags are filed under
+    let mapping = tags::configured_mapping(config)?;
+    let Taxonomy { tags, categories } = tags::taxonomy(mapping.as_ref(), &caption);
 
     // these are the useful bits of the file path
-    let meta_headings = make_headings_from_filepath(post_file_path.to_string());
-
-    let file_stem = Path::new(post_name).file_stem().unwrap().to_str().unwrap();
-
-    // if there are no tags, leave the title alone else prefix with the first tag and a space
-    let title: String;
+    let meta_headings = make_headings_from_filepath(post_file_path.to_string())?;
+
+    let file_stem = post_stem_from_asset(post_name);
+
+    let target = output_target::from_config(config);
+    let post_datetime = get_datetime_from_string(post_name)?;
+    let post_metadata = metadata::find_metadata(&config.input.directory, file_stem);
+    let alt = AltText::new(&config.alt, post_metadata.as_ref(), &caption)?;
+    let title = title::post_title(config, post_name, &caption, &tags, post_metadata.as_ref())?;
+    // the slug that's unique among all the posts, when it could be worked out beforehand
+    let slug = slug.unwrap_or(title.slug);
+
+    // a reused asset is served from the post it was first posted in
+    let source = |asset: &str| -> Result<(String, NaiveDateTime, String), Error> {
+        match reused.get(asset) {
+            Some(original) => Ok((
+                post_stem_from_asset(original).to_string(),
+                get_datetime_from_string(original)?,
+                original.clone(),
+            )),
+            None => Ok((file_stem.to_string(), post_datetime, asset.to_string())),
+        }
+    };
 
-    if tags.len() > 0 {
-        title = format!("{} | {}", tags[0], meta_headings.0);
-    } else {
-        title = meta_headings.0;
-    }
     // prepend the output path to each asset
-    let asset_paths: Vec<String> = asset_list
+    let images: Vec<PostImage> = asset_list
         .iter()
-        .map(|s| format!("/img/instagram/{}/{}", meta_headings.4, s))
-        .collect();
-
-    let thumbnail_image = {
-        format!(
-            "/img/instagram/{}/{}{}{}",
-            meta_headings.4,
-            Path::new(&asset_paths[0])
-                .file_stem()
-                .unwrap()
-                .to_str()
-                .unwrap(),
-            "_thumb.",
-            Path::new(&asset_paths[0])
-                .extension()
-                .unwrap()
-                .to_str()
-                .unwrap()
-        )
-    };
+        .enumerate()
+        .map(|(position, s)| {
+            let index = asset_finder::carousel_index(file_stem, s).unwrap_or(position + 1);
+            let (source_stem, source_datetime, source_asset) = source(s)?;
+            let mut image = post_image(
+                config,
+                target.as_ref(),
+                &source_stem,
+                &source_datetime,
+                index,
+                &source_asset,
+                media.get(s),
+            );
+            image.alt = alt.describe(s, index, asset_list.len(), image.media_type)?;
+            Ok(image)
+        })
+        .collect::<Result<_, Error>>()?;
+
+    // a video without a poster has no thumbnail
+    let thumbnail_image = images
+        .first()
+        .ok_or_else(|| Error::NoMedia(post_name.to_string()))?
+        .thumbnail
+        .clone()
+        .unwrap_or_default();
+    let camera = images.iter().find_map(|image| image.camera.clone());
 
     // render the markdown template
-    let post = Post {
-        title,
+    // the tags come from the whole caption, only the text leaves the trailing ones out
+    let body = match config.captions.strip_trailing_hashtags {
+        true => instagram::strip_trailing_hashtags(&post_file_contents),
+        false => &post_file_contents,
+    };
+    let text = instagram::caption_to_markdown(body, |hashtag| {
+        if !config.captions.link_hashtags {
+            return None;
+        }
+        let tag = match &mapping {
+            Some(mapping) => mapping.tag(hashtag)?,
+            None => hashtag.to_string(),
+        };
+        Some(config.captions.tag_url.replace("{tag}", &tag))
+    });
+
+    let mut post = Post {
+        title: title.text,
         thumbnail_image,
         date: meta_headings.1,
         time_heading: meta_headings.3,
-        categories: vec![String::from("instagram")],
+        categories,
         tags,
         heading: meta_headings.2,
-        text: post_file_contents,
-        images: asset_paths,
-        filename: format!(
-            "{}/{}-{}.md",
-            meta_headings.4,
-            title_prefix.clone(),
-            file_stem
-        ),
+        text,
+        images,
+        filename: target
+            .post_path(file_stem, &post_datetime, &slug)
+            .to_string_lossy()
+            .to_string(),
+        slug,
+        front_matter: String::new(),
+        metadata: post_metadata,
+        camera,
     };
-    post
+    post.front_matter = target.front_matter(&post)?;
+    Ok(post)
+}
+
+// the sizes are those of the files the media stage wrote, a post rendered before it ran has none
+fn post_image(
+    config: &Config,
+    target: &dyn OutputTarget,
+    post_stem: &str,
+    post_datetime: &NaiveDateTime,
+    index: usize,
+    asset: &str,
+    media: Option<&ManifestEntry>,
+) -> PostImage {
+    let url = target.asset_url(post_stem, post_datetime, asset);
+    let placeholder = media.and_then(|media| media.placeholder.clone());
+    let sizes: &[ImageSize] = media.map_or(&[], |media| &media.sizes);
+    if video::is_video(asset) {
+        let info = media.and_then(|media| media.video).unwrap_or_default();
+        // the poster, from Instaloader or extracted, was written along with its sizes
+        let has_poster = !sizes.is_empty();
+        let poster_name = media_processor::poster_filename(asset);
+        let poster = has_poster.then(|| target.asset_url(post_stem, post_datetime, &poster_name));
+        let thumbnail = has_poster.then(|| {
+            let thumbnail = media_processor::thumbnail_filename(&poster_name);
+            target.asset_url(post_stem, post_datetime, &thumbnail)
+        });
+        return PostImage {
+            index,
+            media_type: MediaType::Video,
+            url,
+            thumbnail,
+            width: info.width,
+            height: info.height,
+            aspect_ratio: aspect_ratio(info.width, info.height),
+            orientation: orientation(info.width, info.height),
+            alt: String::new(),
+            srcset: String::new(),
+            sources: Vec::new(),
+            alternates: Vec::new(),
+            is_video: true,
+            poster,
+            duration: info.duration,
+            camera: None,
+            placeholder,
+        };
+    }
+
+    let image_sources = |format: Option<ImageFormat>| -> Vec<ImageSource> {
+        sizes
+            .iter()
+            .map(|size| {
+                let filename = match format {
+                    Some(format) => media_processor::alternate_filename(&size.filename, format),
+                    None => size.filename.clone(),
+                };
+                ImageSource {
+                    url: target.asset_url(post_stem, post_datetime, &filename),
+                    width: size.width,
+                    height: size.height,
+                }
+            })
+            .collect()
+    };
+
+    let alternates = match sizes.is_empty() {
+        true => Vec::new(),
+        false => config
+            .images
+            .formats
+            .iter()
+            .map(|format| {
+                let sources = image_sources(Some(*format));
+                ImageAlternate {
+                    mime_type: format.mime_type().to_string(),
+                    srcset: srcset(&sources),
+                    sources,
+                }
+            })
+            .collect(),
+    };
+    let sources = image_sources(None);
+    // the large image is the last and widest
+    let (width, height) = sizes
+        .last()
+        .map_or((0, 0), |large| (large.width, large.height));
+
+    let thumbnail = media_processor::thumbnail_filename(asset);
+    PostImage {
+        index,
+        media_type: MediaType::Image,
+        url,
+        thumbnail: Some(target.asset_url(post_stem, post_datetime, &thumbnail)),
+        width,
+        height,
+        aspect_ratio: aspect_ratio(width, height),
+        orientation: orientation(width, height),
+        alt: String::new(),
+        srcset: srcset(&sources),
+        sources,
+        alternates,
+        is_video: false,
+        poster: None,
+        duration: None,
+        camera: exif_data::camera_info(&config.input.directory.join(asset)),
+        placeholder,
+    }
+}
+
+fn aspect_ratio(width: u32, height: u32) -> Option<f64> {
+    // four decimals is plenty for CSS aspect-ratio and keeps the output stable
+    (width > 0 && height > 0).then(|| (width as f64 / height as f64 * 10000.0).round() / 10000.0)
+}
+
+fn orientation(width: u32, height: u32) -> Option<Orientation> {
+    match width.cmp(&height) {
+        _ if width == 0 || height == 0 => None,
+        Ordering::Greater => Some(Orientation::Landscape),
+        Ordering::Less => Some(Orientation::Portrait),
+        Ordering::Equal => Some(Orientation::Square),
+    }
 }
 
-pub(crate) fn make_headings_from_filepath(path: String) -> (String, String, String, String, String) {
-    let dt = get_datetime_from_string(&path);
+fn srcset(sources: &[ImageSource]) -> String {
+    sources
+        .iter()
+        .map(|source| format!("{} {}w", source.url, source.width))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+pub(crate) fn make_headings_from_filepath(
+    path: String,
+) -> Result<(String, String, String, String, String), Error> {
+    let dt = get_datetime_from_string(&path)?;
     let title = format!("{}", dt.format("%A, %B %e, %Y"));
     let date = format!("{}", dt.format("%Y-%m-%d"));
     let time_12_hour = format!("{}", dt.format("%l:%M %p"));
     let time_24_hour = format!("{}", dt.format("%H:%M:%S"));
     let output_path = format!("{}", dt.format("%Y/%m/%d"));
-    (
+    Ok((
         title.to_string(),
         date.to_string(),
         time_12_hour.to_string(),
         time_24_hour.to_string(),
         output_path.to_string(),
-    )
+    ))
 }
 
-fn get_datetime_from_string(file_path: &str) -> NaiveDateTime {
+/// The posts earliest first, which is the order their Instaloader names sort in.
+pub(crate) fn in_date_order<T>(posts: &[T], name: impl Fn(&T) -> &str) -> Vec<&T> {
+    let mut posts: Vec<&T> = posts.iter().collect();
+    posts.sort_by(|a, b| name(a).cmp(name(b)));
+    posts
+}
+
+/// The name of the post an asset belongs to, e.g. `2023-06-01_12-30-00_UTC_2.jpg`
+/// belongs to `2023-06-01_12-30-00_UTC`.
+pub(crate) fn post_stem_from_asset(filename: &str) -> &str {
+    let filename = Path::new(filename)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(filename);
+    match filename.find("_UTC") {
+        Some(index) => &filename[..index + "_UTC".len()],
+        None => filename,
+    }
+}
+
+pub(crate) fn get_datetime_from_string(file_path: &str) -> Result<NaiveDateTime, Error> {
     lazy_static! {
         static ref DATETIME_REGEX: Regex = Regex::new(r"(?P<dt>.*)(_UTC)").unwrap();
     }
-    let filename = Path::new(file_path).file_name().unwrap().to_str().unwrap();
-    let captures = DATETIME_REGEX.captures(filename).unwrap();
-    let post_utc_time = NaiveDateTime::parse_from_str(&captures[1], "%Y-%m-%d_%H-%M-%S");
-
-    match post_utc_time {
-        Ok(dt) => {
-            debug!("post_utc_time: {:?}", dt);
-            debug!("date: {:?}", dt.date());
-            debug!("time: {:?}", dt.time());
-        }
-        Err(e) => {
-            error!("Error: {:?}", e);
-            panic!("Error: {:?}", e);
-        }
-    }
-    post_utc_time.unwrap()
+    let invalid = || Error::InvalidPostName(file_path.to_string());
+
+    let filename = Path::new(file_path)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(invalid)?;
+    let captures = DATETIME_REGEX.captures(filename).ok_or_else(invalid)?;
+    let post_utc_time =
+        NaiveDateTime::parse_from_str(&captures[1], "%Y-%m-%d_%H-%M-%S").map_err(|_| invalid())?;
+
+    debug!("post_utc_time: {:?}", post_utc_time);
+    debug!("date: {:?}", post_utc_time.date());
+    debug!("time: {:?}", post_utc_time.time());
+    Ok(post_utc_time)
 }
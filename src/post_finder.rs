@@ -4,76 +4,53 @@ use std::{
     fs,
     path::{Path, PathBuf},
     process::{exit, id},
+    sync::Arc,
 };
 
 use actix::{
     Actor, Context, ContextFutureSpawner, Handler, Message, ResponseActFuture, System, WrapFuture,
 };
 use chrono::NaiveDateTime;
-use dotenvy_macro::dotenv;
 use glob::glob;
 use lazy_static::lazy_static;
 use log::{debug, error, info};
 use regex::{Captures, Regex};
 
-// use crate::{INPUT_DIRECTORY, OUTPUT_DIRECTORY};
+use crate::config::Config;
+use crate::error::Error;
+use crate::{output_target, post_actor};
 
 #[derive(Message)]
-#[rtype(result = "Result<HashMap<String, String>, std::io::Error>")]
+#[rtype(result = "Result<HashMap<String, String>, Error>")]
 pub(crate) struct PostFinderMessage(pub String);
 
-pub(crate) struct PostFinder;
+pub(crate) struct PostFinder {
+    pub config: Arc<Config>,
+}
 
 impl Actor for PostFinder {
     type Context = Context<Self>;
 }
 
 impl Handler<PostFinderMessage> for PostFinder {
-    type Result = Result<HashMap<String, String>, std::io::Error>; // <- Message response type
+    type Result = Result<HashMap<String, String>, Error>; // <- Message response type
 
     fn handle(&mut self, msg: PostFinderMessage, _ctx: &mut Context<Self>) -> Self::Result {
-        let dd = extract_datetime_from_name(msg.0.as_str());
+        let dd = extract_datetime_from_name(msg.0.as_str())?;
 
         // TODO move this into another actor or somewhere else
-        create_output_directories(&dd);
+        create_output_directories(&self.config, &dd)?;
         Ok(dd)
     }
 }
 
-fn extract_datetime_from_name(input: &str) -> HashMap<String, String> {
+fn extract_datetime_from_name(input: &str) -> Result<HashMap<String, String>, Error> {
     let mut datetime_dictionary: HashMap<String, String> = HashMap::with_capacity(5);
-    let file_path = input;
 
-    lazy_static! {
-        static ref DATETIME_REGEX: Regex = Regex::new(r"(?P<dt>.*)(_UTC)").unwrap();
-    }
-    debug!("attempting to match: {:?}", file_path);
-    let captures = DATETIME_REGEX.captures(file_path);
-    match captures {
-        None => {
-            error!("Error: {:?}", "No match");
-            // exit(0);
-        }
-        Some(_) => {
-            debug!("match: {:?}", captures);
-        }
-    }
-    let post_utc_time = NaiveDateTime::parse_from_str(&captures.unwrap()[1], "%Y-%m-%d_%H-%M-%S");
+    debug!("attempting to match: {:?}", input);
+    let dt = post_actor::get_datetime_from_string(input)?;
+    debug!("formatted: {}", dt.format("%Y/%m/%d/"));
 
-    match post_utc_time {
-        Ok(dt) => {
-            debug!("post_utc_time: {:?}", dt);
-            debug!("date: {:?}", dt.date());
-            debug!("time: {:?}", dt.time());
-            let formatted = format!("{}", dt.format("%Y/%m/%d/"));
-            debug!("formatted: {}", formatted);
-        }
-        Err(e) => {
-            error!("Error: {:?}", e);
-            panic!("Error: {:?}", e);
-        }
-    }
-    let dt = post_utc_time.unwrap();
     datetime_dictionary.insert("post_path".to_string(), input.to_string());
     datetime_dictionary.insert(
         "title".to_string(),
@@ -85,24 +62,18 @@ fn extract_datetime_from_name(input: &str) -> HashMap<String, String> {
         "output_path".to_string(),
         format!("{}", dt.format("%Y/%m/%d")),
     );
-    datetime_dictionary
+    Ok(datetime_dictionary)
 }
 
-fn create_output_directories(datetime_dictionary: &HashMap<String, String>) {
-    const OUTPUT_DIRECTORY: &str = dotenv!("OUTPUT_DIRECTORY");
+fn create_output_directories(
+    config: &Config,
+    datetime_dictionary: &HashMap<String, String>,
+) -> Result<(), Error> {
+    let post_path = &datetime_dictionary["post_path"];
+    let post_stem = post_actor::post_stem_from_asset(post_path);
+    let post_datetime = post_actor::get_datetime_from_string(post_path)?;
 
-    let img_output_path: String = format!(
-        "{}/{}/{}/{}/",
-        OUTPUT_DIRECTORY,
-        "img",
-        "instagram",
-        datetime_dictionary.get("output_path").unwrap()
-    );
-    let markdown_output_path: String = format!(
-        "{}/{}/",
-        OUTPUT_DIRECTORY,
-        datetime_dictionary.get("output_path").unwrap()
-    );
-    fs::create_dir_all(img_output_path.clone()).expect("Unable to create img directory");
-    fs::create_dir_all(markdown_output_path.clone()).expect("Unable to create markdown directory");
+    output_target::from_config(config)
+        .create_directories(&config.output.directory, post_stem, &post_datetime)
+        .map_err(Error::io(&config.output.directory))
 }
@@ -0,0 +1,79 @@
+use std::fmt;
+
+use actix::MailboxError;
+use log::warn;
+
+use crate::error::Error;
+
+/// Failures collected across all stages of a run, printed once everything else has been built.
+#[derive(Debug, Default)]
+pub(crate) struct BuildReport {
+    failures: Vec<Failure>,
+}
+
+#[derive(Debug)]
+struct Failure {
+    stage: &'static str,
+    item: String,
+    error: Error,
+}
+
+impl BuildReport {
+    pub(crate) fn add(&mut self, stage: &'static str, item: impl Into<String>, error: Error) {
+        let item = item.into();
+        warn!("{} failed for {}: {}", stage, item, error);
+        self.failures.push(Failure { stage, item, error });
+    }
+
+    /// Split the results of a stage into the successful values, paired with the
+    /// item they were produced for, and failures which are added to the report.
+    ///
+    /// `items` must be in the same order the actor messages were sent.
+    pub(crate) fn collect<T>(
+        &mut self,
+        stage: &'static str,
+        items: Vec<String>,
+        results: Result<Vec<Result<T, Error>>, MailboxError>,
+    ) -> Vec<(String, T)> {
+        match results {
+            Ok(results) => items
+                .into_iter()
+                .zip(results)
+                .filter_map(|(item, result)| match result {
+                    Ok(value) => Some((item, value)),
+                    Err(e) => {
+                        self.add(stage, item, e);
+                        None
+                    }
+                })
+                .collect(),
+            Err(e) => {
+                for item in items {
+                    self.add(stage, item, Error::Mailbox(e));
+                }
+                Vec::new()
+            }
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+impl fmt::Display for BuildReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.failures.len() {
+            1 => writeln!(f, "1 item failed:")?,
+            count => writeln!(f, "{} items failed:", count)?,
+        }
+        for failure in self.failures.iter() {
+            writeln!(
+                f,
+                "  [{}] {}: {}",
+                failure.stage, failure.item, failure.error
+            )?;
+        }
+        Ok(())
+    }
+}
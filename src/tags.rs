@@ -0,0 +1,242 @@
+use std::{collections::BTreeMap, path::Path};
+
+use serde::Deserialize;
+
+use crate::{
+    config::{self, Config},
+    error::Error,
+    instagram,
+};
+
+/// The tag mapping file: how a post's hashtags become its tags and categories.
+///
+/// Hashtags are compared with the names in the file ignoring case and the `#`.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct TagMapping {
+    /// Lowercase every tag, so `#London` and `#london` are one tag either way.
+    pub fold_case: bool,
+    /// Hashtags that aren't tags, e.g. reach tags like `instagood`.
+    pub blocklist: Vec<String>,
+    /// A tag and the hashtags that become it, e.g. `london = ["londonlife"]`.
+    pub aliases: BTreeMap<String, Vec<String>>,
+    /// Categories of every post.
+    pub categories: Vec<String>,
+    /// A category and the tags that put a post in it, those aren't tags as well.
+    pub promote: BTreeMap<String, Vec<String>>,
+}
+
+/// What a post is filed under.
+#[derive(Debug)]
+pub(crate) struct Taxonomy {
+    pub tags: Vec<String>,
+    pub categories: Vec<String>,
+}
+
+/// Where a hashtag ends up.
+enum Mapped<'a> {
+    Tag(String),
+    Category(&'a str),
+    Blocked,
+}
+
+impl Default for TagMapping {
+    fn default() -> Self {
+        TagMapping {
+            fold_case: true,
+            blocklist: Vec::new(),
+            aliases: BTreeMap::new(),
+            categories: vec![String::from("instagram")],
+            promote: BTreeMap::new(),
+        }
+    }
+}
+
+impl TagMapping {
+    /// The tags and categories of a post with these hashtags, with or without their `#`,
+    /// in the order they first appear and without duplicates.
+    pub(crate) fn apply<S: AsRef<str>>(&self, hashtags: &[S]) -> Taxonomy {
+        let mut taxonomy = Taxonomy {
+            tags: Vec::new(),
+            categories: Vec::new(),
+        };
+        for category in self.categories.iter() {
+            push_unique(&mut taxonomy.categories, category);
+        }
+        for hashtag in hashtags {
+            match self.map(hashtag.as_ref()) {
+                Mapped::Tag(tag) => push_unique(&mut taxonomy.tags, &tag),
+                Mapped::Category(category) => push_unique(&mut taxonomy.categories, category),
+                Mapped::Blocked => {}
+            }
+        }
+        taxonomy
+    }
+
+    /// The tag a hashtag becomes, `None` when it's blocked or promoted to a category.
+    pub(crate) fn tag(&self, hashtag: &str) -> Option<String> {
+        match self.map(hashtag) {
+            Mapped::Tag(tag) => Some(tag),
+            _ => None,
+        }
+    }
+
+    fn map(&self, hashtag: &str) -> Mapped<'_> {
+        let hashtag = hashtag.trim_start_matches('#');
+        if contains(&self.blocklist, hashtag) {
+            return Mapped::Blocked;
+        }
+        let tag = match self
+            .aliases
+            .iter()
+            .find(|(_, names)| contains(names, hashtag))
+        {
+            Some((tag, _)) => tag.as_str(),
+            None => hashtag,
+        };
+        if let Some((category, _)) = self
+            .promote
+            .iter()
+            .find(|(_, tags)| contains(tags, tag) || contains(tags, hashtag))
+        {
+            return Mapped::Category(category);
+        }
+        match self.fold_case {
+            true => Mapped::Tag(tag.to_lowercase()),
+            false => Mapped::Tag(tag.to_string()),
+        }
+    }
+}
+
+/// The tags and categories of a caption, with the configured mapping if there is one.
+pub(crate) fn taxonomy(mapping: Option<&TagMapping>, caption: &str) -> Taxonomy {
+    let hashtags_result = instagram::find_hashtags(caption);
+    let hashtags: Vec<&str> = hashtags_result.iter().map(|m| m.as_str()).collect();
+    match mapping {
+        Some(mapping) => mapping.apply(&hashtags),
+        // without a mapping the hashtags are the tags as they're written
+        None => Taxonomy {
+            tags: hashtags
+                .iter()
+                .map(|hashtag| hashtag.trim_start_matches('#').to_string())
+                .collect(),
+            categories: vec![String::from("instagram")],
+        },
+    }
+}
+
+/// The mapping file of `tags.mapping`, if one is configured.
+pub(crate) fn configured_mapping(config: &Config) -> Result<Option<TagMapping>, Error> {
+    config.tags.mapping.as_deref().map(read_mapping).transpose()
+}
+
+/// Read the tag mapping file.
+pub(crate) fn read_mapping(path: &Path) -> Result<TagMapping, Error> {
+    Ok(config::read_toml(path)?)
+}
+
+fn same(a: &str, b: &str) -> bool {
+    a.trim_start_matches('#').to_lowercase() == b.trim_start_matches('#').to_lowercase()
+}
+
+fn contains(names: &[String], name: &str) -> bool {
+    names.iter().any(|other| same(other, name))
+}
+
+// `#London` after `#london` is the same tag, whether or not case is folded
+fn push_unique(names: &mut Vec<String>, name: &str) {
+    if !contains(names, name) {
+        names.push(name.to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mapping() -> TagMapping {
+        TagMapping {
+            blocklist: vec![String::from("instagood")],
+            aliases: BTreeMap::from([(String::from("london"), vec![String::from("LondonLife")])]),
+            promote: BTreeMap::from([(String::from("travel"), vec![String::from("#roadtrip")])]),
+            ..TagMapping::default()
+        }
+    }
+
+    #[test]
+    fn aliases_and_case_become_one_tag() {
+        let taxonomy = mapping().apply(&["#London", "#london", "#londonlife"]);
+        assert_eq!(taxonomy.tags, ["london"]);
+    }
+
+    #[test]
+    fn many_hashtags_alias_one_tag() {
+        let mut mapping = mapping();
+        mapping.aliases.insert(
+            String::from("sky"),
+            vec![String::from("skyporn"), String::from("skylovers")],
+        );
+        let taxonomy = mapping.apply(&["#skyporn", "#skylovers", "#sky"]);
+        assert_eq!(taxonomy.tags, ["sky"]);
+    }
+
+    #[test]
+    fn blocked_hashtags_are_dropped() {
+        let taxonomy = mapping().apply(&["#InstaGood", "#sunset"]);
+        assert_eq!(taxonomy.tags, ["sunset"]);
+        assert_eq!(mapping().tag("#instagood"), None);
+    }
+
+    #[test]
+    fn promoted_tags_are_categories() {
+        let taxonomy = mapping().apply(&["#roadtrip", "#RoadTrip", "#sunset"]);
+        assert_eq!(taxonomy.tags, ["sunset"]);
+        assert_eq!(taxonomy.categories, ["instagram", "travel"]);
+    }
+
+    #[test]
+    fn case_is_kept_without_folding() {
+        let mapping = TagMapping {
+            fold_case: false,
+            ..mapping()
+        };
+        let taxonomy = mapping.apply(&["#NewYork", "#newyork"]);
+        assert_eq!(taxonomy.tags, ["NewYork"]);
+    }
+
+    #[test]
+    fn caption_without_a_mapping() {
+        let taxonomy = taxonomy(None, "Out #London #london #2023");
+        assert_eq!(taxonomy.tags, ["London", "london"]);
+        assert_eq!(taxonomy.categories, ["instagram"]);
+    }
+
+    #[test]
+    fn caption_with_a_mapping() {
+        let taxonomy = taxonomy(
+            Some(&mapping()),
+            "Out #London #londonlife #instagood #roadtrip",
+        );
+        assert_eq!(taxonomy.tags, ["london"]);
+        assert_eq!(taxonomy.categories, ["instagram", "travel"]);
+    }
+
+    #[test]
+    fn mapping_file() {
+        let path = std::env::temp_dir().join(format!("vv-tags-{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            "blocklist = [\"instagood\"]\n[aliases]\nlondon = [\"londonlife\"]\n",
+        )
+        .unwrap();
+        let mapping = read_mapping(&path);
+        std::fs::write(&path, "blocklists = []\n").unwrap();
+        let misspelt = read_mapping(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        let taxonomy = mapping.unwrap().apply(&["#londonlife", "#instagood"]);
+        assert_eq!(taxonomy.tags, ["london"]);
+        assert_eq!(taxonomy.categories, ["instagram"]);
+        assert!(misspelt.is_err());
+    }
+}
@@ -0,0 +1,217 @@
+use image::{DynamicImage, GrayImage, RgbaImage};
+use photon_rs::PhotonImage;
+
+use crate::config::{Dimensions, ThumbnailCrop};
+
+/// The region of `img` that is scaled down to a thumbnail of `thumbnail`'s size,
+/// as `(x, y, width, height)`. It's the largest one with the thumbnail's aspect ratio.
+pub(crate) fn crop_region(
+    mode: ThumbnailCrop,
+    img: &PhotonImage,
+    thumbnail: Dimensions,
+) -> (u32, u32, u32, u32) {
+    let (width, height) = (img.get_width(), img.get_height());
+    let (crop_width, crop_height) = crop_size((width, height), thumbnail);
+    let centred = (
+        (width - crop_width) / 2,
+        (height - crop_height) / 2,
+        crop_width,
+        crop_height,
+    );
+    if mode == ThumbnailCrop::Center || (crop_width, crop_height) == (width, height) {
+        return centred;
+    }
+
+    let Some(rgba) = RgbaImage::from_raw(width, height, img.get_raw_pixels()) else {
+        return centred;
+    };
+    let luma = DynamicImage::ImageRgba8(rgba).to_luma8();
+    if crop_width < width {
+        let x = entropy_offset(width, crop_width, |start, end| {
+            entropy(&luma, (start, 0), (end, height))
+        });
+        (x, 0, crop_width, crop_height)
+    } else {
+        let y = entropy_offset(height, crop_height, |start, end| {
+            entropy(&luma, (0, start), (width, end))
+        });
+        (0, y, crop_width, crop_height)
+    }
+}
+
+fn crop_size(image: (u32, u32), thumbnail: Dimensions) -> (u32, u32) {
+    let (width, height) = image;
+    // compare width / height against thumbnail.width / thumbnail.height without rounding
+    if width as u64 * thumbnail.height as u64 > height as u64 * thumbnail.width as u64 {
+        let crop_width = (height as u64 * thumbnail.width as u64 / thumbnail.height as u64) as u32;
+        (crop_width.max(1), height)
+    } else {
+        let crop_height = (width as u64 * thumbnail.height as u64 / thumbnail.width as u64) as u32;
+        (width, crop_height.max(1))
+    }
+}
+
+// where a window of `wanted` pixels starts along an axis of `length` pixels, found by
+// trimming a strip from whichever end is less detailed until the window is small enough
+fn entropy_offset(length: u32, wanted: u32, strip_entropy: impl Fn(u32, u32) -> f64) -> u32 {
+    let (mut start, mut end) = (0, length);
+    while end - start > wanted {
+        let strip = ((end - start - wanted) / 8).max(1);
+        let first = strip_entropy(start, start + strip);
+        let last = strip_entropy(end - strip, end);
+        if first < last {
+            start += strip;
+        } else {
+            end -= strip;
+        }
+    }
+    start
+}
+
+// Shannon entropy of the grey levels between the corners `from` and `to`
+fn entropy(luma: &GrayImage, from: (u32, u32), to: (u32, u32)) -> f64 {
+    let mut histogram = [0u64; 256];
+    for y in from.1..to.1 {
+        for x in from.0..to.0 {
+            histogram[luma.get_pixel(x, y)[0] as usize] += 1;
+        }
+    }
+    let total: u64 = histogram.iter().sum();
+    if total == 0 {
+        return 0.0;
+    }
+    histogram
+        .iter()
+        .filter(|count| **count > 0)
+        .map(|count| {
+            let p = *count as f64 / total as f64;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SQUARE: Dimensions = Dimensions {
+        width: 100,
+        height: 100,
+    };
+
+    // flat grey, with noise where `busy` says so
+    fn image(width: u32, height: u32, busy: impl Fn(u32, u32) -> bool) -> PhotonImage {
+        let mut seed: u32 = 1;
+        let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                let level = match busy(x, y) {
+                    true => (seed >> 16) as u8,
+                    false => 128,
+                };
+                pixels.extend([level, level, level, 255]);
+            }
+        }
+        PhotonImage::new(pixels, width, height)
+    }
+
+    #[test]
+    fn centred_crops() {
+        let landscape = image(400, 200, |_, _| false);
+        assert_eq!(
+            crop_region(ThumbnailCrop::Center, &landscape, SQUARE),
+            (100, 0, 200, 200)
+        );
+        let portrait = image(200, 400, |_, _| false);
+        assert_eq!(
+            crop_region(ThumbnailCrop::Center, &portrait, SQUARE),
+            (0, 100, 200, 200)
+        );
+        let wide = Dimensions {
+            width: 300,
+            height: 200,
+        };
+        let square = image(400, 400, |_, _| false);
+        assert_eq!(
+            crop_region(ThumbnailCrop::Center, &square, wide),
+            (0, 67, 400, 266)
+        );
+    }
+
+    #[test]
+    fn entropy_crops_keep_the_busy_side() {
+        let busy_right = image(400, 200, |x, _| x >= 300);
+        assert_eq!(
+            crop_region(ThumbnailCrop::Entropy, &busy_right, SQUARE),
+            (200, 0, 200, 200)
+        );
+        let busy_left = image(400, 200, |x, _| x < 100);
+        assert_eq!(
+            crop_region(ThumbnailCrop::Entropy, &busy_left, SQUARE),
+            (0, 0, 200, 200)
+        );
+        let busy_top = image(200, 400, |_, y| y < 100);
+        assert_eq!(
+            crop_region(ThumbnailCrop::Entropy, &busy_top, SQUARE),
+            (0, 0, 200, 200)
+        );
+    }
+
+    #[test]
+    fn entropy_crop_of_the_right_shape_is_the_whole_image() {
+        let square = image(150, 150, |x, _| x > 100);
+        assert_eq!(
+            crop_region(ThumbnailCrop::Entropy, &square, SQUARE),
+            (0, 0, 150, 150)
+        );
+    }
+
+    #[test]
+    fn entropy_offset_finds_the_detail() {
+        // all the detail is between 60 and 80
+        let detail = |start: u32, end: u32| (start.max(60)..end.min(80)).len() as f64;
+        let start = entropy_offset(100, 30, detail);
+        assert!(
+            start <= 60 && start + 30 >= 80,
+            "window starts at {}",
+            start
+        );
+        assert_eq!(entropy_offset(100, 100, detail), 0);
+    }
+
+    #[test]
+    fn regions_stay_inside_the_image() {
+        let thumbnails = [
+            SQUARE,
+            Dimensions {
+                width: 300,
+                height: 200,
+            },
+            Dimensions {
+                width: 200,
+                height: 300,
+            },
+        ];
+        for (width, height) in [(1, 3), (3, 1), (7, 5), (101, 33), (33, 101), (640, 480)] {
+            let img = image(width, height, |x, y| (x + y) % 3 == 0);
+            for mode in [ThumbnailCrop::Center, ThumbnailCrop::Entropy] {
+                for thumbnail in thumbnails {
+                    let (x, y, crop_width, crop_height) = crop_region(mode, &img, thumbnail);
+                    assert!(crop_width > 0 && crop_height > 0);
+                    assert!(
+                        x + crop_width <= width && y + crop_height <= height,
+                        "{:?} {}x{} at {},{} outside {}x{}",
+                        mode,
+                        crop_width,
+                        crop_height,
+                        x,
+                        y,
+                        width,
+                        height
+                    );
+                }
+            }
+        }
+    }
+}
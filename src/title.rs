@@ -0,0 +1,244 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+};
+
+use log::debug;
+use serde::Serialize;
+use tera::Tera;
+
+use crate::{
+    config::{Config, SlugsConfig, TitleStrategy},
+    error::Error,
+    instagram, metadata,
+    metadata::PostMetadata,
+    post_actor::{self, make_headings_from_filepath},
+    tags,
+};
+
+// the slug of a post whose title has no letters or digits at all, e.g. only emoji
+const FALLBACK_SLUG: &str = "post";
+
+/// The title of a post and the slug made from it.
+#[derive(Debug)]
+pub(crate) struct Title {
+    pub text: String,
+    pub slug: String,
+}
+
+// what `titles.template` can use
+#[derive(Serialize)]
+struct TemplateContext<'a> {
+    tags: &'a [String],
+    sentence: &'a str,
+    location: Option<&'a str>,
+    date: &'a str,
+}
+
+/// The title of the post `post_name`, whose caption is `caption`.
+pub(crate) fn post_title(
+    config: &Config,
+    post_name: &str,
+    caption: &str,
+    tags: &[String],
+    metadata: Option<&PostMetadata>,
+) -> Result<Title, Error> {
+    let date = make_headings_from_filepath(post_name.to_string())?.0;
+    let sentence = instagram::first_sentence(caption);
+    let location = metadata
+        .and_then(|metadata| metadata.location.as_ref())
+        .map(|location| location.name.trim())
+        .filter(|name| !name.is_empty());
+
+    let subject = match config.titles.strategy {
+        TitleStrategy::Hashtag => None,
+        TitleStrategy::Sentence => Some(sentence.as_str()).filter(|s| !s.is_empty()),
+        TitleStrategy::Location => location,
+        TitleStrategy::Template => {
+            let context = TemplateContext {
+                tags,
+                sentence: &sentence,
+                location,
+                date: &date,
+            };
+            let context = tera::Context::from_serialize(context)?;
+            let text = Tera::one_off(&config.titles.template, &context, false)?;
+            let text = text.split_whitespace().collect::<Vec<&str>>().join(" ");
+            let slug = slugify(&config.slugs, &text);
+            return Ok(Title { text, slug });
+        }
+    };
+    match subject.or_else(|| tags.first().map(String::as_str)) {
+        Some(subject) => Ok(Title {
+            text: format!("{} | {}", subject, date),
+            slug: slugify(&config.slugs, subject),
+        }),
+        // the first words of the caption still tell apart the posts of a day
+        None => Ok(Title {
+            slug: slugify(
+                &config.slugs,
+                &sentence
+                    .split_whitespace()
+                    .take(2)
+                    .collect::<Vec<&str>>()
+                    .join(" "),
+            ),
+            text: date,
+        }),
+    }
+}
+
+/// Lowercase ASCII words of `text` joined by hyphens, without stop words, cut at the
+/// word before `max_length`.
+pub(crate) fn slugify(config: &SlugsConfig, text: &str) -> String {
+    let text = unidecode::unidecode(text).to_lowercase();
+    let words: Vec<&str> = text
+        .split(|ch: char| !ch.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect();
+    let kept: Vec<&str> = words
+        .iter()
+        .copied()
+        .filter(|word| !config.stop_words.iter().any(|stop| stop == word))
+        .collect();
+    let words = match kept.is_empty() {
+        true => words,
+        false => kept,
+    };
+    match truncate(&words.join("-"), config.max_length) {
+        "" => FALLBACK_SLUG.to_string(),
+        slug => slug.to_string(),
+    }
+}
+
+// at the last hyphen that keeps it short enough, or mid-word when the first word is too long
+fn truncate(slug: &str, max_length: usize) -> &str {
+    if slug.len() <= max_length {
+        return slug;
+    }
+    match slug[..=max_length].rfind('-') {
+        Some(end) if end > 0 => &slug[..end],
+        _ => &slug[..max_length],
+    }
+}
+
+/// The slug of every post, by caption filename, `-2`, `-3` and so on added to the
+/// slug of a post when an earlier one has it already.
+///
+/// A post whose title can't be made is left out, rendering it reports why.
+pub(crate) fn unique_slugs(config: &Config, posts: &[String]) -> HashMap<String, String> {
+    let mut slugs = HashMap::new();
+    let mapping = match tags::configured_mapping(config) {
+        Ok(mapping) => mapping,
+        Err(_) => return slugs,
+    };
+    let needs_metadata = matches!(
+        config.titles.strategy,
+        TitleStrategy::Location | TitleStrategy::Template
+    );
+
+    let mut taken: HashSet<String> = HashSet::new();
+    for post in post_actor::in_date_order(posts, String::as_str) {
+        let caption = match fs::read_to_string(config.input.directory.join(post)) {
+            Ok(caption) => caption,
+            Err(_) => continue,
+        };
+        let taxonomy = tags::taxonomy(mapping.as_ref(), &caption);
+        let post_stem = post_actor::post_stem_from_asset(post);
+        let metadata = match needs_metadata {
+            true => metadata::find_metadata(&config.input.directory, post_stem),
+            false => None,
+        };
+        let title = match post_title(config, post, &caption, &taxonomy.tags, metadata.as_ref()) {
+            Ok(title) => title,
+            Err(e) => {
+                debug!("{}: no title: {}", post, e);
+                continue;
+            }
+        };
+
+        let mut slug = title.slug.clone();
+        let mut number = 1;
+        while taken.contains(&slug) {
+            number += 1;
+            let suffix = format!("-{}", number);
+            let base = truncate(&title.slug, config.slugs.max_length - suffix.len());
+            slug = format!("{}{}", base, suffix);
+        }
+        if number > 1 {
+            debug!("{}: slug {} is taken, using {}", post, title.slug, slug);
+        }
+        taken.insert(slug.clone());
+        slugs.insert(post.clone(), slug);
+    }
+    slugs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slugs_are_ascii_words() {
+        let config = SlugsConfig::default();
+        assert_eq!(slugify(&config, "Café in Tōkyō!"), "cafe-tokyo");
+        assert_eq!(slugify(&config, "東京"), "dong-jing");
+        assert_eq!(slugify(&config, "The sun & the Sea, 2023"), "sun-sea-2023");
+    }
+
+    #[test]
+    fn slugs_keep_stop_words_when_nothing_else_is_left() {
+        let config = SlugsConfig::default();
+        assert_eq!(slugify(&config, "The End"), "end");
+        assert_eq!(slugify(&config, "To be or not"), "be-not");
+        assert_eq!(slugify(&config, "in the"), "in-the");
+    }
+
+    #[test]
+    fn slugs_without_letters_fall_back() {
+        assert_eq!(slugify(&SlugsConfig::default(), "🌅 🌊"), FALLBACK_SLUG);
+    }
+
+    #[test]
+    fn slugs_are_cut_between_words() {
+        let config = SlugsConfig {
+            max_length: 10,
+            ..SlugsConfig::default()
+        };
+        assert_eq!(slugify(&config, "sunset over london"), "sunset");
+        assert_eq!(slugify(&config, "sunset over"), "sunset");
+        assert_eq!(slugify(&config, "supercalifragilistic"), "supercalif");
+    }
+
+    #[test]
+    fn truncate_at_a_hyphen() {
+        assert_eq!(truncate("sunset-over", 11), "sunset-over");
+        assert_eq!(truncate("sunset-over", 10), "sunset");
+        assert_eq!(truncate("sunset-over", 6), "sunset");
+        assert_eq!(truncate("sunset-over", 5), "sunse");
+        assert_eq!(truncate("-sunset", 3), "-su");
+    }
+
+    #[test]
+    fn unique_slugs_number_the_later_posts() {
+        let directory = std::env::temp_dir().join(format!("vv-slugs-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let posts = [
+            "2023-06-02_08-00-00_UTC.txt",
+            "2023-06-01_08-00-00_UTC.txt",
+            "2023-06-03_08-00-00_UTC.txt",
+        ];
+        for post in posts {
+            fs::write(directory.join(post), "Out and about #London").unwrap();
+        }
+        let mut config = Config::default();
+        config.input.directory = directory.clone();
+        let posts: Vec<String> = posts.iter().map(|post| post.to_string()).collect();
+
+        let slugs = unique_slugs(&config, &posts);
+        fs::remove_dir_all(&directory).unwrap();
+        assert_eq!(slugs["2023-06-01_08-00-00_UTC.txt"], "london");
+        assert_eq!(slugs["2023-06-02_08-00-00_UTC.txt"], "london-2");
+        assert_eq!(slugs["2023-06-03_08-00-00_UTC.txt"], "london-3");
+    }
+}
@@ -0,0 +1,289 @@
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use log::debug;
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+
+/// Size and length of an mp4, read from its `moov` box without decoding any frames.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+pub(crate) struct VideoInfo {
+    pub width: u32,
+    pub height: u32,
+    /// Length in seconds.
+    pub duration: Option<f64>,
+}
+
+pub(crate) fn is_video(filename: &str) -> bool {
+    Path::new(filename)
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("mp4"))
+}
+
+/// The poster Instaloader saves next to a video, `<stem>.jpg` for `<stem>.mp4`.
+pub(crate) fn sidecar_poster(input_directory: &Path, filename: &str) -> Option<PathBuf> {
+    let poster = input_directory.join(Path::new(filename).with_extension("jpg"));
+    poster.is_file().then_some(poster)
+}
+
+/// Grab the first frame of a video with ffmpeg, for videos without a sidecar poster.
+pub(crate) fn extract_poster(video: &Path, poster: &Path) -> Result<(), Error> {
+    let output = Command::new("ffmpeg")
+        .args(["-loglevel", "error", "-y", "-i"])
+        .arg(video)
+        .args(["-frames:v", "1"])
+        .arg(poster)
+        .output()
+        .map_err(|e| {
+            Error::Image(
+                video.to_path_buf(),
+                format!("no sidecar poster and unable to run ffmpeg: {}", e),
+            )
+        })?;
+    if !output.status.success() {
+        return Err(Error::Image(
+            video.to_path_buf(),
+            format!(
+                "ffmpeg couldn't extract a poster: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+        ));
+    }
+    debug!("extracted poster {}", poster.display());
+    Ok(())
+}
+
+pub(crate) fn read_video_info(path: &Path) -> Result<VideoInfo, Error> {
+    let invalid = |message: &str| Error::Image(path.to_path_buf(), message.to_string());
+    let mut file = File::open(path).map_err(Error::io(path))?;
+    let file_length = file.metadata().map_err(Error::io(path))?.len();
+
+    // the moov box can come before or after the (large) media data, so skip over the rest
+    let mut position = 0;
+    while position < file_length {
+        let mut header = [0u8; 8];
+        file.read_exact(&mut header).map_err(Error::io(path))?;
+        let mut size = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as u64;
+        let mut header_length = 8;
+        if size == 1 {
+            let mut large_size = [0u8; 8];
+            file.read_exact(&mut large_size).map_err(Error::io(path))?;
+            size = u64::from_be_bytes(large_size);
+            header_length = 16;
+        } else if size == 0 {
+            size = file_length - position;
+        }
+        // a corrupt size would otherwise be allocated before reading fails
+        if size < header_length || size > file_length - position {
+            return Err(invalid("corrupt mp4 box"));
+        }
+
+        if &header[4..8] == b"moov" {
+            let mut moov = vec![0u8; (size - header_length) as usize];
+            file.read_exact(&mut moov).map_err(Error::io(path))?;
+            return parse_moov(&moov).ok_or_else(|| invalid("unreadable mp4 moov box"));
+        }
+        position += size;
+        file.seek(SeekFrom::Start(position))
+            .map_err(Error::io(path))?;
+    }
+    Err(invalid("no moov box, not an mp4"))
+}
+
+fn parse_moov(moov: &[u8]) -> Option<VideoInfo> {
+    let mut info = VideoInfo::default();
+    for (kind, body) in boxes(moov) {
+        match kind {
+            b"mvhd" => info.duration = parse_mvhd(body),
+            b"trak" => {
+                let dimensions = boxes(body)
+                    .find(|(kind, _)| *kind == b"tkhd")
+                    .and_then(|(_, tkhd)| parse_tkhd(tkhd));
+                // audio tracks have no size, the first track with one is the video
+                if let Some((width, height)) = dimensions.filter(|_| info.width == 0) {
+                    info.width = width;
+                    info.height = height;
+                }
+            }
+            _ => {}
+        }
+    }
+    (info.width > 0).then_some(info)
+}
+
+// the child boxes in a box's body, as (type, body)
+fn boxes(data: &[u8]) -> impl Iterator<Item = (&[u8; 4], &[u8])> {
+    let mut rest = data;
+    std::iter::from_fn(move || {
+        let size = u32::from_be_bytes(rest.get(0..4)?.try_into().ok()?) as usize;
+        let kind: &[u8; 4] = rest.get(4..8)?.try_into().ok()?;
+        let body = rest.get(8..size)?;
+        rest = &rest[size..];
+        Some((kind, body))
+    })
+}
+
+fn parse_mvhd(mvhd: &[u8]) -> Option<f64> {
+    let read_u32 = |at: usize| Some(u32::from_be_bytes(mvhd.get(at..at + 4)?.try_into().ok()?));
+    let read_u64 = |at: usize| Some(u64::from_be_bytes(mvhd.get(at..at + 8)?.try_into().ok()?));
+    // version 1 has 64 bit times and duration
+    let (timescale, duration) = match mvhd.first()? {
+        1 => (read_u32(20)?, read_u64(24)?),
+        _ => (read_u32(12)?, read_u32(16)? as u64),
+    };
+    (timescale > 0).then(|| duration as f64 / timescale as f64)
+}
+
+fn parse_tkhd(tkhd: &[u8]) -> Option<(u32, u32)> {
+    let matrix_start = match tkhd.first()? {
+        1 => 52,
+        _ => 40,
+    };
+    let read_i32 = |at: usize| Some(i32::from_be_bytes(tkhd.get(at..at + 4)?.try_into().ok()?));
+    // width and height are 16.16 fixed point, after the 3x3 display matrix
+    let width = (read_i32(matrix_start + 36)? >> 16) as u32;
+    let height = (read_i32(matrix_start + 40)? >> 16) as u32;
+    if width == 0 || height == 0 {
+        return None;
+    }
+    // phones record portrait video as landscape with a 90 degree rotation in the matrix
+    let (a, b) = (read_i32(matrix_start)?, read_i32(matrix_start + 4)?);
+    if a == 0 && b != 0 {
+        Some((height, width))
+    } else {
+        Some((width, height))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mp4_box(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut data = ((body.len() + 8) as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(kind);
+        data.extend_from_slice(body);
+        data
+    }
+
+    fn mvhd(timescale: u32, duration: u32) -> Vec<u8> {
+        let mut body = vec![0u8; 12];
+        body.extend(timescale.to_be_bytes());
+        body.extend(duration.to_be_bytes());
+        body.resize(100, 0);
+        mp4_box(b"mvhd", &body)
+    }
+
+    // a version 0 tkhd, `rotated` by 90 degrees the way phones record portrait video
+    fn tkhd(width: u32, height: u32, rotated: bool) -> Vec<u8> {
+        let mut body = vec![0u8; 84];
+        let (a, b) = match rotated {
+            true => (0, 0x0001_0000),
+            false => (0x0001_0000, 0),
+        };
+        body[40..44].copy_from_slice(&(a as u32).to_be_bytes());
+        body[44..48].copy_from_slice(&(b as u32).to_be_bytes());
+        body[76..80].copy_from_slice(&(width << 16).to_be_bytes());
+        body[80..84].copy_from_slice(&(height << 16).to_be_bytes());
+        mp4_box(b"tkhd", &body)
+    }
+
+    fn info(name: &str, data: &[u8]) -> Result<VideoInfo, String> {
+        let path =
+            std::env::temp_dir().join(format!("vv-video-{}-{}.mp4", std::process::id(), name));
+        std::fs::write(&path, data).unwrap();
+        let info = read_video_info(&path).map_err(|e| match e {
+            Error::Image(_, message) => message,
+            e => e.to_string(),
+        });
+        std::fs::remove_file(&path).unwrap();
+        info
+    }
+
+    fn mp4(moov: &[u8]) -> Vec<u8> {
+        let mut data = mp4_box(b"ftyp", b"isom\0\0\0\0");
+        data.extend(mp4_box(b"mdat", &[0u8; 64]));
+        data.extend(mp4_box(b"moov", moov));
+        data
+    }
+
+    #[test]
+    fn size_and_duration() {
+        let mut moov = mvhd(1000, 12500);
+        moov.extend(mp4_box(b"trak", &tkhd(1920, 1080, false)));
+        let info = info("landscape", &mp4(&moov)).unwrap();
+        assert_eq!(
+            info,
+            VideoInfo {
+                width: 1920,
+                height: 1080,
+                duration: Some(12.5)
+            }
+        );
+    }
+
+    #[test]
+    fn rotated_video_is_portrait() {
+        let mut moov = mvhd(600, 300);
+        // the audio track comes first and has no size
+        moov.extend(mp4_box(b"trak", &tkhd(0, 0, false)));
+        moov.extend(mp4_box(b"trak", &tkhd(1920, 1080, true)));
+        let info = info("portrait", &mp4(&moov)).unwrap();
+        assert_eq!((info.width, info.height), (1080, 1920));
+        assert_eq!(info.duration, Some(0.5));
+    }
+
+    #[test]
+    fn version_1_mvhd() {
+        let mut body = vec![1u8, 0, 0, 0];
+        body.extend([0u8; 16]);
+        body.extend(90000u32.to_be_bytes());
+        body.extend(180000u64.to_be_bytes());
+        body.resize(112, 0);
+        let mut moov = mp4_box(b"mvhd", &body);
+        moov.extend(mp4_box(b"trak", &tkhd(640, 640, false)));
+        assert_eq!(info("version1", &mp4(&moov)).unwrap().duration, Some(2.0));
+    }
+
+    #[test]
+    fn box_larger_than_the_file() {
+        let mut data = mp4_box(b"ftyp", b"isom\0\0\0\0");
+        data.extend(0xffff_fff0u32.to_be_bytes());
+        data.extend(b"moov");
+        data.extend([0u8; 32]);
+        assert_eq!(info("corrupt", &data).unwrap_err(), "corrupt mp4 box");
+
+        // a 64 bit size as well
+        let mut data = mp4_box(b"ftyp", b"isom\0\0\0\0");
+        data.extend(1u32.to_be_bytes());
+        data.extend(b"mdat");
+        data.extend(u64::MAX.to_be_bytes());
+        assert_eq!(info("corrupt64", &data).unwrap_err(), "corrupt mp4 box");
+    }
+
+    #[test]
+    fn truncated_box() {
+        let mut data = mp4(&mvhd(1000, 1000));
+        data.truncate(data.len() - 10);
+        assert_eq!(info("truncated", &data).unwrap_err(), "corrupt mp4 box");
+    }
+
+    #[test]
+    fn not_an_mp4() {
+        let data = mp4_box(b"ftyp", b"isom\0\0\0\0");
+        assert_eq!(
+            info("nomoov", &data).unwrap_err(),
+            "no moov box, not an mp4"
+        );
+        let moov = mvhd(1000, 1000);
+        assert_eq!(
+            info("notrack", &mp4(&moov)).unwrap_err(),
+            "unreadable mp4 moov box"
+        );
+    }
+}
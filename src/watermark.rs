@@ -0,0 +1,334 @@
+use std::{collections::BTreeMap, fs, path::Path};
+
+use image::{imageops, Rgba, RgbaImage};
+use log::debug;
+use photon_rs::PhotonImage;
+use rusttype::{point, Font, PositionedGlyph, Scale};
+
+use crate::{
+    config::{self, Anchor, Config, WatermarkConfig, WatermarkOverride},
+    error::Error,
+    instagram, post_actor,
+};
+
+// width of a text watermark, as a fraction of the image, when no scale is configured
+const DEFAULT_TEXT_SCALE: f32 = 0.25;
+
+/// The mark stamped on every large image, either an image or text rendered from a TrueType font.
+pub(crate) enum Watermark {
+    Image(RgbaImage),
+    Text {
+        font: Font<'static>,
+        text: String,
+        color: [u8; 3],
+    },
+}
+
+impl Watermark {
+    pub(crate) fn load(config: &WatermarkConfig) -> Result<Watermark, Error> {
+        match (&config.text, &config.font) {
+            (Some(text), Some(font_path)) => {
+                let data = fs::read(font_path).map_err(Error::io(font_path))?;
+                let font = Font::try_from_vec(data).ok_or_else(|| {
+                    Error::Image(font_path.clone(), String::from("not a TrueType font"))
+                })?;
+                Ok(Watermark::Text {
+                    font,
+                    text: text.clone(),
+                    color: config.rgb().unwrap_or([255, 255, 255]),
+                })
+            }
+            _ => {
+                let mark = image::open(&config.image)
+                    .map_err(|e| Error::Image(config.image.clone(), e.to_string()))?;
+                Ok(Watermark::Image(mark.to_rgba8()))
+            }
+        }
+    }
+
+    /// Stamp the watermark onto `img`, images smaller than the configured minimum are returned as they are.
+    pub(crate) fn apply(&self, config: &WatermarkConfig, img: PhotonImage) -> PhotonImage {
+        let (width, height) = (img.get_width(), img.get_height());
+        if width < config.minimum.width || height < config.minimum.height {
+            debug!("{}x{} is below the watermark minimum", width, height);
+            return img;
+        }
+        let Some(mut canvas) = RgbaImage::from_raw(width, height, img.get_raw_pixels()) else {
+            return img;
+        };
+
+        let mut mark = self.render(config, width);
+        if config.opacity < 1.0 {
+            for pixel in mark.pixels_mut() {
+                pixel[3] = (pixel[3] as f32 * config.opacity).round() as u8;
+            }
+        }
+
+        let (x, y) = placement(config, (width, height), mark.dimensions());
+        imageops::overlay(&mut canvas, &mark, x, y);
+        PhotonImage::new(canvas.into_raw(), width, height)
+    }
+
+    // the mark at the size it's stamped onto an image `image_width` pixels wide
+    fn render(&self, config: &WatermarkConfig, image_width: u32) -> RgbaImage {
+        let target_width = |scale: f32| ((image_width as f32 * scale).round() as u32).max(1);
+        match self {
+            Watermark::Image(mark) => match config.scale {
+                Some(scale) => {
+                    let mark_width = target_width(scale);
+                    let mark_height = ((mark.height() as f32 * mark_width as f32
+                        / mark.width() as f32)
+                        .round() as u32)
+                        .max(1);
+                    imageops::resize(
+                        mark,
+                        mark_width,
+                        mark_height,
+                        imageops::FilterType::Lanczos3,
+                    )
+                }
+                None => mark.clone(),
+            },
+            Watermark::Text { font, text, color } => {
+                // measure at a known size, then render at the size that gives the wanted width
+                let reference = Scale::uniform(100.0);
+                let reference_width = text_width(&layout(font, text, reference)).max(1.0);
+                let wanted = target_width(config.scale.unwrap_or(DEFAULT_TEXT_SCALE));
+                let px = 100.0 * wanted as f32 / reference_width;
+                render_text(font, text, *color, Scale::uniform(px))
+            }
+        }
+    }
+}
+
+/// The watermark settings for one asset once the overrides file and rules have
+/// been applied, `None` when the asset isn't watermarked at all.
+pub(crate) fn resolve(config: &Config, filepath: &str) -> Result<Option<WatermarkConfig>, Error> {
+    let watermark = &config.watermark;
+    let post_stem = post_actor::post_stem_from_asset(filepath);
+    let asset_stem = Path::new(filepath)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or(post_stem);
+
+    let overrides = match &watermark.overrides {
+        Some(path) => read_overrides(path)?,
+        None => BTreeMap::new(),
+    };
+    let change = match overrides
+        .get(asset_stem)
+        .or_else(|| overrides.get(post_stem))
+    {
+        Some(change) => Some(change.clone()),
+        None if watermark.rules.is_empty() => None,
+        None => {
+            let date = post_actor::get_datetime_from_string(post_stem)?.date();
+            let hashtags = post_hashtags(config, post_stem)?;
+            watermark
+                .rules
+                .iter()
+                .find(|rule| {
+                    rule.since.is_none_or(|since| date >= since)
+                        && rule.until.is_none_or(|until| date <= until)
+                        && rule.hashtag.as_ref().is_none_or(|hashtag| {
+                            let hashtag = hashtag.trim_start_matches('#').to_lowercase();
+                            hashtags.contains(&hashtag)
+                        })
+                })
+                .map(|rule| rule.change())
+        }
+    };
+
+    let mut resolved = watermark.clone();
+    match change {
+        Some(change) if change.skip => {
+            debug!("{}: not watermarked", filepath);
+            return Ok(None);
+        }
+        Some(change) => {
+            debug!("{}: watermark changed to {:?}", filepath, change);
+            if let Some(image) = change.image {
+                resolved.image = image;
+                resolved.text = None;
+            }
+            if change.text.is_some() {
+                resolved.text = change.text;
+            }
+        }
+        None => {}
+    }
+    Ok(Some(resolved))
+}
+
+/// Read the per-post watermark overrides, a TOML table keyed by post or asset stem.
+pub(crate) fn read_overrides(path: &Path) -> Result<BTreeMap<String, WatermarkOverride>, Error> {
+    Ok(config::read_toml(path)?)
+}
+
+// lowercased hashtags of the post's caption, without the `#`
+fn post_hashtags(config: &Config, post_stem: &str) -> Result<Vec<String>, Error> {
+    let caption_path = config.input.directory.join(format!("{}.txt", post_stem));
+    let caption = match fs::read_to_string(&caption_path) {
+        Ok(caption) => caption,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(Error::Io(caption_path, e)),
+    };
+    Ok(instagram::find_hashtags(&caption)
+        .iter()
+        .map(|m| m.as_str().trim_start_matches('#').to_lowercase())
+        .collect())
+}
+
+fn layout<'a>(font: &'a Font<'static>, text: &str, scale: Scale) -> Vec<PositionedGlyph<'a>> {
+    let ascent = font.v_metrics(scale).ascent;
+    font.layout(text, scale, point(0.0, ascent)).collect()
+}
+
+fn text_width(glyphs: &[PositionedGlyph<'_>]) -> f32 {
+    glyphs
+        .last()
+        .map(|glyph| glyph.position().x + glyph.unpositioned().h_metrics().advance_width)
+        .unwrap_or(0.0)
+}
+
+fn render_text(font: &Font<'static>, text: &str, color: [u8; 3], scale: Scale) -> RgbaImage {
+    let v_metrics = font.v_metrics(scale);
+    let glyphs = layout(font, text, scale);
+    let width = (text_width(&glyphs).ceil() as u32).max(1);
+    let height = ((v_metrics.ascent - v_metrics.descent).ceil() as u32).max(1);
+
+    let mut mark = RgbaImage::from_pixel(width, height, Rgba([color[0], color[1], color[2], 0]));
+    for glyph in glyphs.iter() {
+        let Some(bounds) = glyph.pixel_bounding_box() else {
+            continue;
+        };
+        glyph.draw(|x, y, coverage| {
+            let (px, py) = (x as i32 + bounds.min.x, y as i32 + bounds.min.y);
+            if px < 0 || py < 0 || px >= width as i32 || py >= height as i32 {
+                return;
+            }
+            let pixel = mark.get_pixel_mut(px as u32, py as u32);
+            // overlapping glyphs keep the strongest coverage
+            pixel[3] = pixel[3].max((coverage * 255.0).round() as u8);
+        });
+    }
+    mark
+}
+
+// top left corner of the mark, which stays inside the image when it's larger than the margin allows
+fn placement(config: &WatermarkConfig, image: (u32, u32), mark: (u32, u32)) -> (u32, u32) {
+    let margin = (image.0 as f32 * config.margin / 100.0).round() as u32;
+    let free_x = image.0.saturating_sub(mark.0);
+    let free_y = image.1.saturating_sub(mark.1);
+
+    let x = match config.anchor {
+        Anchor::TopLeft | Anchor::Left | Anchor::BottomLeft => margin.min(free_x),
+        Anchor::Top | Anchor::Center | Anchor::Bottom => free_x / 2,
+        Anchor::TopRight | Anchor::Right | Anchor::BottomRight => free_x.saturating_sub(margin),
+    };
+    let y = match config.anchor {
+        Anchor::TopLeft | Anchor::Top | Anchor::TopRight => margin.min(free_y),
+        Anchor::Left | Anchor::Center | Anchor::Right => free_y / 2,
+        Anchor::BottomLeft | Anchor::Bottom | Anchor::BottomRight => free_y.saturating_sub(margin),
+    };
+    (x, y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Dimensions;
+
+    fn config(anchor: Anchor) -> WatermarkConfig {
+        WatermarkConfig {
+            anchor,
+            margin: 2.0,
+            ..WatermarkConfig::default()
+        }
+    }
+
+    #[test]
+    fn placement_at_each_anchor() {
+        // a 2% margin of a 1000 pixel wide image is 20 pixels
+        let cases = [
+            (Anchor::TopLeft, (20, 20), (10, 5)),
+            (Anchor::Top, (450, 20), (5, 5)),
+            (Anchor::TopRight, (880, 20), (0, 5)),
+            (Anchor::Left, (20, 225), (10, 2)),
+            (Anchor::Center, (450, 225), (5, 2)),
+            (Anchor::Right, (880, 225), (0, 2)),
+            (Anchor::BottomLeft, (20, 430), (10, 0)),
+            (Anchor::Bottom, (450, 430), (5, 0)),
+            (Anchor::BottomRight, (880, 430), (0, 0)),
+        ];
+        for (anchor, small, too_large) in cases {
+            let config = config(anchor);
+            assert_eq!(
+                placement(&config, (1000, 500), (100, 50)),
+                small,
+                "{:?}",
+                anchor
+            );
+            // only 10 by 5 pixels are left around the mark, less than the margin
+            assert_eq!(
+                placement(&config, (1000, 500), (990, 495)),
+                too_large,
+                "{:?}",
+                anchor
+            );
+            assert_eq!(
+                placement(&config, (1000, 500), (2000, 1000)),
+                (0, 0),
+                "{:?}",
+                anchor
+            );
+        }
+    }
+
+    fn grey(width: u32, height: u32) -> PhotonImage {
+        let pixels = [128, 128, 128, 255].repeat((width * height) as usize);
+        PhotonImage::new(pixels, width, height)
+    }
+
+    #[test]
+    fn small_images_are_left_alone() {
+        let mark = Watermark::Image(RgbaImage::from_pixel(10, 10, Rgba([255, 0, 0, 255])));
+        let config = WatermarkConfig {
+            minimum: Dimensions {
+                width: 200,
+                height: 100,
+            },
+            ..config(Anchor::TopLeft)
+        };
+        for (width, height) in [(199, 400), (400, 99)] {
+            let marked = mark.apply(&config, grey(width, height));
+            assert_eq!(
+                marked.get_raw_pixels(),
+                grey(width, height).get_raw_pixels()
+            );
+        }
+        let marked = mark.apply(&config, grey(200, 100));
+        assert_ne!(marked.get_raw_pixels(), grey(200, 100).get_raw_pixels());
+    }
+
+    #[test]
+    fn marks_are_scaled_and_translucent() {
+        let mark = Watermark::Image(RgbaImage::from_pixel(10, 5, Rgba([255, 255, 255, 255])));
+        let config = WatermarkConfig {
+            scale: Some(0.5),
+            opacity: 0.5,
+            minimum: Dimensions {
+                width: 0,
+                height: 0,
+            },
+            ..config(Anchor::Center)
+        };
+        assert_eq!(mark.render(&config, 100).dimensions(), (50, 25));
+
+        let marked = mark.apply(&config, grey(100, 100));
+        let canvas = RgbaImage::from_raw(100, 100, marked.get_raw_pixels()).unwrap();
+        // half white over grey, and untouched outside the mark
+        assert!((190..=192).contains(&canvas.get_pixel(50, 50)[0]));
+        assert_eq!(canvas.get_pixel(5, 5)[0], 128);
+    }
+}